@@ -1,38 +1,341 @@
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+
 use bytes::Bytes;
-use screeps_api::RoomName;
-use structopt::StructOpt;
+use structopt::{
+    clap::{self, ErrorKind},
+    StructOpt,
+};
+
+use crate::{
+    net::Error,
+    room::Location,
+    ui::{Theme, TileMode},
+};
+
+mod profile;
 
 fn bytes_from_str(v: &str) -> Bytes {
     Bytes::copy_from_slice(v.as_bytes())
 }
 
+/// An inclusive range of game ticks, written as `<start>..<end>`.
+#[derive(Clone, Copy, Debug)]
+pub struct TickRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl FromStr for TickRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, "..");
+        let mut tick = || -> Result<u32, String> {
+            let part = parts.next().unwrap_or("");
+            part.trim()
+                .parse()
+                .map_err(|e| format!("invalid tick {:?}: {}", part, e))
+        };
+        let range = TickRange {
+            start: tick()?,
+            end: tick()?,
+        };
+        if range.start > range.end {
+            return Err(format!("range {} ends before it starts", range));
+        }
+        Ok(range)
+    }
+}
+
+impl fmt::Display for TickRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
 #[derive(Clone, Debug, StructOpt)]
 #[structopt(name = "srv", about = "screeps room view client")]
 pub struct Config {
-    /// A token to authentication to the server with
+    /// A token to authentication to the server with (SRV_TOKEN is used if no other credentials
+    /// are given)
     #[structopt(short = "t", long = "token", parse(from_str = bytes_from_str))]
-    pub auth_token: Bytes,
+    pub auth_token: Option<Bytes>,
+    /// Read the token from this file
+    #[structopt(long = "token-file", parse(from_os_str), conflicts_with = "auth-token")]
+    pub token_file: Option<PathBuf>,
+    /// Run this shell command and use its output as the token, like `pass show screeps`
+    #[structopt(long = "token-command", conflicts_with_all = &["auth-token", "token-file"])]
+    pub token_command: Option<String>,
+    /// Sign in with this username and a password instead of a token, for private servers
+    #[structopt(
+        long = "username",
+        conflicts_with_all = &["auth-token", "token-file", "token-command"]
+    )]
+    pub username: Option<String>,
+    /// Password for --username, from a profile or prompted for
+    #[structopt(skip)]
+    pub password: Option<String>,
+    /// Config file to read profiles from (default is ~/.config/srv/config.toml)
+    #[structopt(long = "config", parse(from_os_str))]
+    pub config_file: Option<PathBuf>,
+    /// Profile from the config file to use (default is the file's default_profile)
+    #[structopt(short = "p", long = "profile")]
+    pub profile: Option<String>,
     /// The server to connect to (default is https://screeps.com/api/)
     #[structopt(short = "u", long = "server")]
     pub server: Option<String>,
     /// The shard to watch the room on - must be specified for the default server
     #[structopt(short = "s", long = "shard")]
     pub shard: Option<String>,
-    /// The room to watch
-    #[structopt(short = "r", long = "room", parse(try_from_str = RoomName::new))]
-    pub room: Option<RoomName>,
+    /// The room to watch, like W12N5, shard2/W12N5 or W12N5 25 13 to also place the cursor
+    #[structopt(short = "r", long = "room")]
+    pub room: Option<Location>,
     /// Increase log verbosity
     #[structopt(short = "v", parse(from_occurrences))]
     pub verbosity: u64,
+    /// Number of past ticks of the current room to keep for scrubbing back through
+    #[structopt(long = "history-ticks", default_value = "100")]
+    pub history_ticks: usize,
     /// Disable UI
     #[structopt(short = "d", long = "dry-run")]
     pub dry_run: bool,
+    /// Record raw server traffic to this file (the auth token is not recorded)
+    #[structopt(long = "record", parse(from_os_str))]
+    pub record: Option<PathBuf>,
+    /// Replay a file written by --record instead of connecting to a server
+    #[structopt(long = "replay", parse(from_os_str), conflicts_with = "record")]
+    pub replay: Option<PathBuf>,
+    /// Play back the server's room history for a range of ticks, like 1000..1200 (needs --room)
+    #[structopt(long = "history", conflicts_with_all = &["record", "replay"])]
+    pub history: Option<TickRange>,
+    /// Draw rooms with only ASCII, for terminals which draw some glyphs two columns wide
+    #[structopt(long = "ascii")]
+    pub ascii: bool,
+    /// How to draw room tiles: normal, wide for two columns per tile so rooms look square, or
+    /// compact for two tiles per cell on small terminals
+    #[structopt(long = "tiles")]
+    pub tiles: Option<TileMode>,
+    /// Theme file with glyphs and colors for drawing rooms (default is ~/.config/srv/theme.toml)
+    #[structopt(long = "theme", parse(from_os_str))]
+    pub theme_file: Option<PathBuf>,
+    /// Loaded from --theme or the default theme file
+    #[structopt(skip)]
+    pub theme: Theme,
+}
+
+impl Config {
+    /// Fills in anything not given on the command line from the selected profile.
+    fn apply_profile(&mut self) -> Result<(), Error> {
+        let path = match self.config_file.clone().or_else(profile::default_path) {
+            Some(path) => path,
+            None if self.profile.is_some() => return Err("no config directory found".into()),
+            None => return Ok(()),
+        };
+        let required = self.config_file.is_some() || self.profile.is_some();
+        let profile = match profile::load(&path, self.profile.as_ref().map(|s| &**s), required)? {
+            Some(profile) => profile,
+            None => return Ok(()),
+        };
+
+        // any credentials on the command line replace all of the profile's
+        if self.auth_token.is_none()
+            && self.token_file.is_none()
+            && self.token_command.is_none()
+            && self.username.is_none()
+        {
+            self.auth_token = profile.token.as_ref().map(|t| bytes_from_str(t));
+            self.token_file = profile.token_file;
+            self.token_command = profile.token_command;
+            self.username = profile.username;
+            self.password = profile.password;
+        }
+        if self.server.is_none() {
+            self.server = profile.server;
+        }
+        if self.shard.is_none() {
+            self.shard = profile.shard;
+        }
+        if self.room.is_none() {
+            self.room = profile.room;
+        }
+        if self.verbosity == 0 {
+            self.verbosity = profile.verbosity.unwrap_or(0);
+        }
+        if !self.ascii {
+            self.ascii = profile.ascii.unwrap_or(false);
+        }
+        if self.tiles.is_none() {
+            self.tiles = profile.tiles;
+        }
+        Ok(())
+    }
+
+    /// Reads the token from its file or command if it wasn't given directly, or asks for the
+    /// password to sign in with. SRV_TOKEN is only used when nothing else was given.
+    fn resolve_token(&mut self) -> Result<(), Error> {
+        if self.auth_token.is_some() {
+            return Ok(());
+        }
+        if let Some(path) = &self.token_file {
+            self.auth_token = Some(read_token_file(path)?);
+        } else if let Some(command) = &self.token_command {
+            self.auth_token = Some(run_token_command(command)?);
+        } else if let Some(username) = &self.username {
+            if self.password.is_none() {
+                // this has to happen before the UI takes over the terminal
+                let prompt = format!("Password for {}: ", username);
+                let password = rpassword::read_password_from_tty(Some(&prompt))
+                    .map_err(|e| format!("reading password: {}", e))?;
+                self.password = Some(password);
+            }
+        } else if let Some(token) = env::var("SRV_TOKEN").ok().filter(|t| !t.is_empty()) {
+            self.auth_token = Some(bytes_from_str(&token));
+        }
+        Ok(())
+    }
+
+    /// Whether there's nothing to sign in with, so the setup wizard should run first.
+    pub fn needs_setup(&self) -> bool {
+        self.auth_token.is_none()
+            && self.username.is_none()
+            && self.replay.is_none()
+            && self.history.is_none()
+    }
+
+    /// Saves the server, credentials and shard as a profile in the config file, and returns
+    /// where it was saved.
+    ///
+    /// Passwords aren't saved, they're prompted for when the profile is used.
+    pub fn save_profile(&mut self, name: &str) -> Result<PathBuf, Error> {
+        let path = self
+            .config_file
+            .clone()
+            .or_else(profile::default_path)
+            .ok_or("no config directory found")?;
+        let profile = profile::Profile {
+            server: self.server.clone(),
+            token: self
+                .auth_token
+                .as_ref()
+                .map(|t| String::from_utf8_lossy(t).into_owned()),
+            username: self.username.clone(),
+            shard: self.shard.clone(),
+            ..Default::default()
+        };
+        profile::save(&path, name, &profile)?;
+        self.profile = Some(name.to_owned());
+        Ok(path)
+    }
+
+    /// Checks requirements which can be met by either the command line or a profile.
+    fn validate(&self) -> Result<(), String> {
+        // without a UI there's no way to run the setup wizard
+        if self.dry_run && self.needs_setup() {
+            return Err("a token is required: use --token, SRV_TOKEN, --token-file, \
+                 --token-command, --username or a profile"
+                .to_owned());
+        }
+        if self.history.is_some() && self.room.is_none() {
+            return Err("--history requires a room, from --room or a profile".to_owned());
+        }
+        Ok(())
+    }
+}
+
+fn read_token_file(path: &Path) -> Result<Bytes, Error> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("reading token file {}: {}", path.display(), e))?;
+    non_empty_token(&contents, || format!("token file {} is empty", path.display()))
+}
+
+/// Runs a command through the shell, taking the token from its output.
+fn run_token_command(command: &str) -> Result<Bytes, Error> {
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(&["/C", command]).output()
+    } else {
+        Command::new("sh").args(&["-c", command]).output()
+    }
+    .map_err(|e| format!("running token command {:?}: {}", command, e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "token command {:?} failed with {}: {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    let stdout = String::from_utf8(output.stdout)
+        .map_err(|_| format!("token command {:?} printed invalid UTF-8", command))?;
+    non_empty_token(&stdout, || format!("token command {:?} printed nothing", command))
+}
+
+fn non_empty_token(text: &str, empty_message: impl FnOnce() -> String) -> Result<Bytes, Error> {
+    match text.trim() {
+        "" => Err(empty_message().into()),
+        token => Ok(bytes_from_str(token)),
+    }
 }
 
 pub fn setup() -> Config {
-    let conf = Config::from_args();
+    let mut conf = Config::from_args();
+
+    if let Err(e) = conf.apply_profile() {
+        clap::Error::with_description(&format!("couldn't load profile: {}", e), ErrorKind::Io)
+            .exit();
+    }
+    if let Err(e) = conf.resolve_token() {
+        clap::Error::with_description(&format!("couldn't get token: {}", e), ErrorKind::Io)
+            .exit();
+    }
+    if let Err(e) = conf.validate() {
+        clap::Error::with_description(&e, ErrorKind::MissingRequiredArgument).exit();
+    }
 
     crate::logging::setup_logging(conf.verbosity);
 
+    // after logging, so that ignored glyphs are logged
+    match Theme::load(conf.theme_file.as_ref().map(|p| &**p), conf.ascii) {
+        Ok(theme) => conf.theme = theme,
+        Err(e) => {
+            clap::Error::with_description(&format!("couldn't load theme: {}", e), ErrorKind::Io)
+                .exit()
+        }
+    }
+
     return conf;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::TickRange;
+
+    fn parse(s: &str) -> Result<(u32, u32), String> {
+        s.parse::<TickRange>().map(|range| (range.start, range.end))
+    }
+
+    #[test]
+    fn tick_range_parses() {
+        assert_eq!(parse("1000..1200"), Ok((1000, 1200)));
+        assert_eq!(parse(" 1000 .. 1000 "), Ok((1000, 1000)));
+    }
+
+    #[test]
+    fn tick_range_rejects_bad_input() {
+        assert_eq!(
+            parse("1200..1000"),
+            Err("range 1200..1000 ends before it starts".to_owned())
+        );
+        assert!(parse("1000").is_err());
+        assert!(parse("..1000").is_err());
+        assert!(parse("1000..").is_err());
+        assert!(parse("a..b").is_err());
+        assert!(parse("-5..10").is_err());
+    }
+}
@@ -0,0 +1,171 @@
+//! Named server profiles from the config file.
+//!
+//! ```toml
+//! default_profile = "official"
+//!
+//! [profiles.official]
+//! server = "https://screeps.com/api/"
+//! token = "..." # or token_file = "...", or token_command = "..."
+//!
+//! [profiles.private]
+//! server = "http://localhost:21025/api/"
+//! username = "me" # password = "..." is optional, it's prompted for otherwise
+//! shard = "shard3"
+//! room = "W12N5"
+//! log_level = "debug"
+//! ascii = true
+//! tiles = "wide"
+//! ```
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use err_ctx::ResultExt;
+
+use crate::{net::Error, room::Location, storage, ui::TileMode};
+
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    pub server: Option<String>,
+    pub token: Option<String>,
+    pub token_file: Option<PathBuf>,
+    pub token_command: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub shard: Option<String>,
+    pub room: Option<Location>,
+    /// Log verbosity, like the number of `-v` flags.
+    pub verbosity: Option<u64>,
+    pub ascii: Option<bool>,
+    pub tiles: Option<TileMode>,
+}
+
+/// `config.toml` in the config directory.
+pub fn default_path() -> Option<PathBuf> {
+    storage::config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// Loads the profile called `name`, or the file's default profile if no name is given.
+///
+/// A missing file is only an error when it's `required`, like when it was given with `--config`
+/// or a profile was asked for by name.
+pub fn load(path: &Path, name: Option<&str>, required: bool) -> Result<Option<Profile>, Error> {
+    let text = match fs::read_to_string(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound && !required => return Ok(None),
+        res => res.with_ctx(|_| format!("reading {}", path.display()))?,
+    };
+    let file = text
+        .parse::<toml::Value>()
+        .with_ctx(|_| format!("parsing {}", path.display()))?;
+
+    let name = match name.or_else(|| file.get("default_profile")?.as_str()) {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let profile = file
+        .get("profiles")
+        .and_then(|profiles| profiles.get(name))
+        .ok_or_else(|| format!("no profile named {:?} in {}", name, path.display()))?;
+
+    let string = |key: &str| -> Result<Option<String>, Error> {
+        match profile.get(key) {
+            None => Ok(None),
+            Some(toml::Value::String(s)) => Ok(Some(s.clone())),
+            Some(_) => Err(format!("{} in profile {:?} must be a string", key, name).into()),
+        }
+    };
+    let ascii = match profile.get("ascii") {
+        None => None,
+        Some(toml::Value::Boolean(ascii)) => Some(*ascii),
+        Some(_) => return Err(format!("ascii in profile {:?} must be true or false", name).into()),
+    };
+    let room = match string("room")? {
+        Some(room) => Some(
+            room.parse::<Location>()
+                .map_err(|e| format!("invalid room in profile {:?}: {}", name, e))?,
+        ),
+        None => None,
+    };
+    let tiles = match string("tiles")? {
+        Some(tiles) => Some(
+            tiles
+                .parse::<TileMode>()
+                .map_err(|e| format!("invalid tiles in profile {:?}: {}", name, e))?,
+        ),
+        None => None,
+    };
+    let verbosity = match string("log_level")?.as_ref().map(|s| &**s) {
+        None => None,
+        Some("info") => Some(0),
+        Some("debug") => Some(1),
+        Some("trace") => Some(2),
+        Some(other) => {
+            return Err(format!(
+                "invalid log_level {:?} in profile {:?}, expected info, debug or trace",
+                other, name
+            )
+            .into())
+        }
+    };
+
+    Ok(Some(Profile {
+        server: string("server")?,
+        token: string("token")?,
+        token_file: string("token_file")?.map(PathBuf::from),
+        token_command: string("token_command")?,
+        username: string("username")?,
+        password: string("password")?,
+        shard: string("shard")?,
+        room,
+        verbosity,
+        ascii,
+        tiles,
+    }))
+}
+
+/// Adds or replaces the profile called `name`, making it the default if there isn't one yet.
+///
+/// Other profiles are kept, but comments in the file are lost.
+pub fn save(path: &Path, name: &str, profile: &Profile) -> Result<(), Error> {
+    let mut file = match fs::read_to_string(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => toml::value::Table::new(),
+        res => {
+            let text = res.with_ctx(|_| format!("reading {}", path.display()))?;
+            toml::from_str(&text).with_ctx(|_| format!("parsing {}", path.display()))?
+        }
+    };
+
+    let mut table = toml::value::Table::new();
+    let mut set = |key: &str, value: Option<String>| {
+        if let Some(value) = value {
+            table.insert(key.to_owned(), toml::Value::String(value));
+        }
+    };
+    set("server", profile.server.clone());
+    set("token", profile.token.clone());
+    set(
+        "token_file",
+        profile.token_file.as_ref().map(|p| p.display().to_string()),
+    );
+    set("token_command", profile.token_command.clone());
+    set("username", profile.username.clone());
+    set("password", profile.password.clone());
+    set("shard", profile.shard.clone());
+
+    let profiles = file
+        .entry("profiles")
+        .or_insert_with(|| toml::Value::Table(Default::default()));
+    match profiles {
+        toml::Value::Table(profiles) => {
+            profiles.insert(name.to_owned(), toml::Value::Table(table));
+        }
+        _ => return Err(format!("profiles in {} must be a table", path.display()).into()),
+    }
+    file.entry("default_profile")
+        .or_insert_with(|| toml::Value::String(name.to_owned()));
+
+    let text = toml::to_string(&toml::Value::Table(file))?;
+    storage::write_file(path, text.as_bytes())
+}
@@ -2,4 +2,6 @@ pub mod config;
 mod logging;
 pub mod net;
 mod room;
+mod session;
+mod storage;
 pub mod ui;
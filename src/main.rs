@@ -11,10 +11,16 @@ fn main() {
     } else {
         Cursive::default()
     };
-    ui::setup(&mut siv);
-    net::spawn(config.clone(), siv.cb_sink().clone());
+    if config.needs_setup() {
+        // the wizard starts everything else once a profile is saved
+        ui::setup_wizard(&mut siv, config);
+    } else {
+        ui::setup(&mut siv, &config);
+        net::spawn(config, siv.cb_sink().clone());
+    }
 
     debug!("running srv ui");
     siv.run();
+    ui::save_session();
     debug!("srv ui exited normally");
 }
@@ -1,21 +1,22 @@
-use std::thread;
+use std::{mem, thread};
 
+use bytes::Bytes;
 use cursive::CbSink;
-use err_ctx::ResultExt;
+use err_ctx::{ErrorExt, ResultExt};
 use futures::{
     channel::mpsc::unbounded,
     compat::{Future01CompatExt, Sink01CompatExt, Stream01CompatExt},
     future::{self, Either},
     stream, FutureExt, Sink, SinkExt, Stream, StreamExt, TryStreamExt,
 };
-use hyper::client::HttpConnector;
+use hyper::{client::HttpConnector, StatusCode};
 use hyper_tls::HttpsConnector;
 use log::{debug, error, info, warn};
 use old_futures::stream::Stream as OldStream;
 use screeps_api::{
     websocket::{
         commands::{subscribe, unsubscribe},
-        Channel, ChannelUpdate, ScreepsMessage, SockjsMessage,
+        Channel, ScreepsMessage, SockjsMessage,
     },
     Api, MyInfo, RoomName, TokenStorage,
 };
@@ -23,25 +24,126 @@ use websocket::{ClientBuilder, OwnedMessage};
 
 use crate::{
     config::Config,
-    room::{ConnectionState, Room, RoomId},
+    room::{owned_rooms_from_json, ConnectionState, MapStats, RoomId, Terrain},
+    session::SessionKey,
     ui::{self, CursiveStatePair},
 };
 
+mod backoff;
+mod history;
+mod http;
+mod playback;
+mod record;
+mod replay;
+mod rooms;
+
+use self::{backoff::Backoff, record::Recorder, rooms::RoomManager};
+
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
 
+/// An error which reconnecting won't fix.
+#[derive(Debug, derive_more::Display)]
+struct Fatal(Error);
+
+impl std::error::Error for Fatal {}
+
+fn fatal<E: Into<Error>>(e: E) -> Error {
+    Box::new(Fatal(e.into()))
+}
+
+fn fatal_if_unauthorized(e: screeps_api::Error) -> Error {
+    match e.kind() {
+        screeps_api::ErrorKind::Unauthorized => fatal(e),
+        _ => e.into(),
+    }
+}
+
+fn is_fatal(e: &Error) -> bool {
+    e.is::<Fatal>()
+}
+
+/// Fetches a room's terrain. While recording it's fetched as raw JSON instead, so that the
+/// recording holds the response the terrain was read from.
+async fn request_terrain(
+    client: &Api<HttpsConnector<HttpConnector>>,
+    http: &http::HttpClient,
+    recorder: Option<&mut Recorder>,
+    room_id: &RoomId,
+) -> Result<Terrain, Error> {
+    let recorder = match recorder {
+        Some(recorder) => recorder,
+        None => {
+            let terrain = client
+                .room_terrain(room_id.shard.as_ref(), room_id.room_name.to_string())
+                .await
+                .with_ctx(|_| format!("fetching {} terrain", room_id))?;
+            return Ok(Terrain::from(&terrain));
+        }
+    };
+    let mut url = client.url.join("game/room-terrain")?;
+    url.query_pairs_mut()
+        .append_pair("room", &room_id.room_name.to_string())
+        .append_pair("encoded", "true");
+    if let Some(shard) = &room_id.shard {
+        url.query_pairs_mut().append_pair("shard", shard);
+    }
+    let response = http::get_json_authenticated(http, url.as_str(), client.token_storage())
+        .await
+        .with_ctx(|_| format!("fetching {} terrain", room_id))?;
+    recorder.room_terrain(room_id.shard.as_ref(), &response);
+    Ok(Terrain::from_response(&response).with_ctx(|_| format!("reading {} terrain", room_id))?)
+}
+
+/// Reads the user info screeps_api would have parsed from a raw `auth/me` response.
+fn my_info_from_response(response: &serde_json::Value) -> Result<MyInfo, Error> {
+    if response["ok"].as_i64() != Some(1) {
+        return Err(format!("auth/me returned {}", response).into());
+    }
+    let info = serde_json::from_value(serde_json::json!({
+        "user_id": response["_id"],
+        "username": response["username"],
+        "has_password": response["password"],
+        "cpu": response["cpu"],
+        "gcl_points": response["gcl"],
+        "credits": response["money"],
+        "shard_allocations": null,
+    }))
+    .with_ctx(|_| "reading auth/me response")?;
+    Ok(info)
+}
+
 #[derive(Clone, Debug)]
 pub enum Command {
     /// Command sent by net internals indicating that the connection should be re-established.
     Reconnect,
     ChangeRoom(RoomId),
     ChangeShard(String),
+    /// Shows, or stops showing, the eight rooms around the current one.
+    SetNeighborhood(bool),
+    /// Fetches map stats for these rooms, and watches them until the map is closed.
+    OpenMap(Vec<RoomId>),
+    CloseMap,
     FetchShardNames,
+    /// Runs a JavaScript expression in the user console on the current room's shard.
+    ExecuteConsole(String),
+    /// Pauses or resumes an offline playback.
+    PlaybackTogglePause,
+    /// Skips to the next step of an offline playback, even when paused.
+    PlaybackStep,
+    /// Doubles the speed of an offline playback.
+    PlaybackFaster,
+    /// Halves the speed of an offline playback.
+    PlaybackSlower,
 }
 
 pub fn spawn(config: Config, ui: CbSink) {
     thread::spawn(|| {
         let err_ui_sink = ui.clone();
-        let res = run(config, ui);
+        let res = match (&config.replay, config.history) {
+            (Some(path), _) => replay::run(path, ui),
+            (None, Some(ticks)) => history::run(&config, ticks, ui),
+            (None, None) => run(config, ui),
+        };
 
         if let Err(e) = res {
             error!("Error occurred: {0} ({0:?})", e);
@@ -57,21 +159,121 @@ fn run(config: Config, ui: CbSink) -> Result<(), Error> {
     Ok(())
 }
 
+/// Signs in with a username and password, as servers using screepsmod-auth expect.
+async fn sign_in(
+    http: &http::HttpClient,
+    url: &str,
+    username: &str,
+    password: &str,
+) -> Result<Bytes, Error> {
+    let body = serde_json::json!({ "email": username, "password": password });
+    let data = match http::post_json_unauthenticated(http, url, &body).await {
+        Ok(data) => data,
+        Err(e) => {
+            // trying again can fix a network error, but not a wrong password
+            let rejected = e
+                .downcast_ref::<http::StatusError>()
+                .map_or(false, |e| e.status == StatusCode::UNAUTHORIZED);
+            let e = Error::from(e.ctx(format!("signing in as {}", username)));
+            return Err(if rejected { fatal(e) } else { e });
+        }
+    };
+    let token = data["token"]
+        .as_str()
+        .ok_or_else(|| fatal(format!("signing in as {} returned no token", username)))?;
+    Ok(Bytes::copy_from_slice(token.as_bytes()))
+}
+
+/// How the setup wizard signs in.
+#[derive(Clone, Debug)]
+pub enum Credentials {
+    Token(Bytes),
+    SignIn { username: String, password: String },
+}
+
+/// What checking a server and credentials found.
+#[derive(Clone, Debug)]
+pub struct AccountCheck {
+    pub username: String,
+    /// Empty for servers without shards.
+    pub shards: Vec<String>,
+}
+
+/// Checks that the credentials work on a server, and lists its shards.
+///
+/// Blocks until done, so the setup wizard runs this on its own thread.
+pub fn check_account(
+    server: Option<&str>,
+    credentials: &Credentials,
+) -> Result<AccountCheck, Error> {
+    let mut runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(check_account_async(server, credentials))
+}
+
+async fn check_account_async(
+    server: Option<&str>,
+    credentials: &Credentials,
+) -> Result<AccountCheck, Error> {
+    let hyper = hyper::Client::builder().build::<_, hyper::Body>(HttpsConnector::new());
+    let mut client = Api::new(hyper.clone());
+    if let Some(u) = server {
+        client.set_url(u)?;
+    }
+    let token = match credentials {
+        Credentials::Token(token) => token.clone(),
+        Credentials::SignIn { username, password } => {
+            let url = client.url.join("auth/signin")?;
+            sign_in(&hyper, url.as_str(), username, password).await?
+        }
+    };
+    client.set_token(token);
+
+    let user = client
+        .my_info()?
+        .await
+        .with_ctx(|_| "checking credentials")?;
+    // private servers don't always have shards
+    let shards = match client.shard_list().await {
+        Ok(shards) => shards.into_iter().map(|info| info.name).collect(),
+        Err(e) => {
+            debug!("couldn't list shards, assuming there are none: {}", e);
+            Vec::new()
+        }
+    };
+    Ok(AccountCheck {
+        username: user.username,
+        shards,
+    })
+}
+
 struct Stage1 {
     config: Config,
     client: Api<HttpsConnector<HttpConnector>>,
+    http: http::HttpClient,
     ui: CbSink,
+    recorder: Option<Recorder>,
+    /// Where the last session for this server and token is saved.
+    session: Option<SessionKey>,
 }
 
 #[allow(unused)]
 struct ConnIndepState {
     config: Config,
-    room_id: RoomId,
     client: Api<HttpsConnector<HttpConnector>>,
+    http: http::HttpClient,
     ui: CbSink,
     tokens: TokenStorage,
     user: MyInfo,
-    room: Room,
+    rooms: RoomManager,
+    /// Rooms shown on the world map, subscribed to for their map views.
+    map_rooms: Vec<RoomId>,
+    /// The user's owned and reserved rooms, always subscribed to for their map views.
+    owned_rooms: Vec<RoomId>,
+    /// Whether the current websocket connection has been authenticated.
+    authenticated: bool,
+    /// Set after signing in again because the token was rejected, until the new one works.
+    signed_in_again: bool,
+    recorder: Option<Recorder>,
 }
 
 struct Connected<Si, St> {
@@ -84,17 +286,48 @@ impl Stage1 {
     pub fn new(config: Config, ui: CbSink) -> Result<Self, Error> {
         let hyper = hyper::Client::builder().build::<_, hyper::Body>(HttpsConnector::new());
 
-        let mut client = Api::new(hyper);
+        let mut client = Api::new(hyper.clone());
 
         if let Some(u) = &config.server {
             client.set_url(u)?;
         }
-        client.set_token(config.auth_token.clone());
+        match (&config.auth_token, &config.username) {
+            (Some(token), _) => client.set_token(token.clone()),
+            // signing in waits for fetch_initial, so that network errors are retried
+            (None, Some(_)) => (),
+            (None, None) => return Err("an auth token or username is required".into()),
+        }
 
         let server = client.url.to_string();
+        let bookmarks_server = server.clone();
+        ui::async_update(&ui, |s| s.load_bookmarks(bookmarks_server))?;
+
+        // sessions of users who sign in are kept by username, since each sign in gets a new token
+        let identity = match (&config.auth_token, &config.username) {
+            (Some(token), _) => Some(&token[..]),
+            (None, Some(username)) => Some(username.as_bytes()),
+            (None, None) => None,
+        };
+        let session = identity.and_then(|identity| SessionKey::new(&server, identity));
+        if let Some(key) = session.clone() {
+            ui::async_update(&ui, |s| s.session_key(key))?;
+        }
+
+        let recorder = match &config.record {
+            Some(path) => Some(Recorder::create(path, &server)?),
+            None => None,
+        };
+
         ui::async_update(&ui, |s| s.server(server))?;
 
-        Ok(Stage1 { config, client, ui })
+        Ok(Stage1 {
+            config,
+            client,
+            http: hyper,
+            ui,
+            recorder,
+            session,
+        })
     }
 
     pub fn run(self) {
@@ -115,44 +348,8 @@ impl Stage1 {
         })))
     }
 
-    async fn run_tokio(self) -> Result<(), Error> {
-        use screeps_api::{
-            websocket::{transform_url, *},
-            DEFAULT_OFFICIAL_API_URL,
-        };
-        let tokens = self.client.token_storage().clone();
-
-        // info.user_id allows subscribing to messages.
-        let user = self.client.my_info()?.await?;
-
-        let ui_user = user.clone();
-        ui::async_update(&self.ui, |s| s.user(ui_user))?;
-
-        let (shard, room) = match (self.config.shard.as_ref(), self.config.room.as_ref()) {
-            (shard, Some(room)) => (shard.cloned(), room.clone()),
-            (Some(shard), None) => {
-                let room_name = self.client.shard_start_room(shard)?.await?.room_name;
-                let room_name = RoomName::new(&room_name).map_err(|e| e.into_owned())?;
-                (Some(shard.clone()), room_name)
-            }
-            (None, None) => {
-                let start_room = self.client.world_start_room()?.await?;
-                let room_name = RoomName::new(&start_room.room_name).map_err(|e| e.into_owned())?;
-                (start_room.shard, room_name)
-            }
-        };
-
-        let room_id = RoomId::new(shard, room);
-
-        debug!("starting at room {}", room_id);
-
-        let terrain = self
-            .client
-            .room_terrain(room_id.shard.as_ref(), room_id.room_name.to_string())
-            .await
-            .with_ctx(|_| format!("fetching {} terrain", room_id))?;
-
-        debug!("successfully authenticated as {}", user.username);
+    async fn run_tokio(mut self) -> Result<(), Error> {
+        use screeps_api::{websocket::transform_url, DEFAULT_OFFICIAL_API_URL};
 
         let ws_url = self
             .config
@@ -163,73 +360,269 @@ impl Stage1 {
 
         let ws_url = transform_url(ws_url).ctx("parsing API url")?;
 
-        let room = Room::new(room_id.clone(), terrain);
-
         let (cmd_send, cmd_recv) = unbounded();
 
         ui::async_update(&self.ui, |s| s.command_sender(cmd_send))?;
 
+        let mut backoff = Backoff::new();
+
+        let (user, room_id, terrain) = loop {
+            match self.fetch_initial().await {
+                Ok(v) => break v,
+                Err(e) if is_fatal(&e) => return Err(e),
+                Err(e) => {
+                    warn!("initial connection failed: {}", e);
+                    backoff.wait(&self.ui).await?;
+                }
+            }
+        };
+        backoff.reset();
+
+        let rooms = RoomManager::new(self.ui.clone(), user.user_id.clone(), room_id, terrain);
+
         let mut s = ConnIndepState {
+            tokens: self.client.token_storage().clone(),
             config: self.config,
             client: self.client,
+            http: self.http,
             ui: self.ui,
-            room_id,
-            tokens,
             user,
-            room,
+            rooms,
+            map_rooms: Vec::new(),
+            owned_rooms: Vec::new(),
+            authenticated: false,
+            signed_in_again: false,
+            recorder: self.recorder,
         };
 
+        // the owned rooms list is a convenience, so don't stop if it fails
+        if let Err(e) = s.fetch_owned_rooms().await {
+            warn!("fetching owned rooms failed: {}", e);
+        }
+
         let mut cmd_recv = cmd_recv.map(|cmd| Ok(Either::Right(cmd)));
 
         loop {
-            let (conn, _) = ClientBuilder::from_url(&ws_url.as_str().parse().unwrap())
-                .async_connect(None)
-                .compat()
-                .await?;
+            let res = match s.connect(ws_url.as_str()).await {
+                Ok((sink, stream)) => {
+                    // Listen to both the network stream and our commands
+                    let stream = stream::select(stream, cmd_recv);
+
+                    let mut conn = Connected { s, sink, stream };
+                    debug!("stage 1 handing off");
+                    let res = conn.run().await;
+                    // recapture state
+                    s = conn.s;
+                    cmd_recv = conn.stream.into_inner().1;
+                    res
+                }
+                Err(e) => Err(e),
+            };
 
-            let (sink, stream) = conn.split();
-            let mut sink = sink.sink_compat().sink_map_err(Error::from);
-            let stream = stream.compat().map_err(Error::from);
+            match res {
+                Ok(()) => debug!("stage 2 ended, stage 1 reconnecting"),
+                Err(e) if is_fatal(&e) => return Err(e),
+                Err(e) => warn!("connection failed, reconnecting: {}", e),
+            }
+            if s.authenticated {
+                backoff.reset();
+            }
 
-            // If we didn't have this, then the loop over this stream would just be waiting for commands
-            // after the network stream stops. This makes sure that if the network stream is disconnected,
-            // then we immediately get a 'Reconnect' message after that.
-            let stream = stream
-                .map(|res| res.map(Either::Left))
-                .chain(stream::once(future::ok(Either::Right(Command::Reconnect))));
+            s.update_ui(|s| s.conn_state(ConnectionState::Disconnected))?;
+            backoff.wait(&s.ui).await?;
+        }
+    }
 
-            // Listen to both the network stream and our commands
-            let stream = stream::select(stream, cmd_recv);
+    /// Fetches everything needed before connecting: user info, start room and its terrain.
+    async fn fetch_initial(&mut self) -> Result<(MyInfo, RoomId, Terrain), Error> {
+        if let (None, Some(username)) = (self.client.token_storage().get(), &self.config.username) {
+            let url = self.client.url.join("auth/signin")?;
+            let password = self.config.password.as_ref().map_or("", |p| &**p);
+            let token = sign_in(&self.http, url.as_str(), username, password).await?;
+            self.client.set_token(token);
+        }
 
-            s.update_ui(|s| s.conn_state(ConnectionState::Authenticating))?;
+        // info.user_id allows subscribing to messages.
+        let user = match &mut self.recorder {
+            Some(recorder) => {
+                let url = self.client.url.join("auth/me")?;
+                let tokens = self.client.token_storage();
+                let response = http::get_json_authenticated(&self.http, url.as_str(), tokens)
+                    .await
+                    .with_ctx(|_| "fetching user info")?;
+                recorder.my_info(&response);
+                my_info_from_response(&response)?
+            }
+            None => self
+                .client
+                .my_info()
+                .map_err(fatal)?
+                .await
+                .map_err(fatal_if_unauthorized)?,
+        };
 
-            sink.send(OwnedMessage::Text(commands::authenticate(
-                &s.tokens.get().unwrap(),
-            )))
-            .await?;
-            sink.send(OwnedMessage::Text(commands::subscribe(
-                &Channel::room_detail(s.room_id.room_name, s.room_id.shard.as_ref()),
-            )))
-            .await?;
-            sink.send(OwnedMessage::Text(commands::subscribe(
-                &Channel::user_console(&s.user.user_id),
-            )))
-            .await?;
+        let ui_user = user.clone();
+        ui::async_update(&self.ui, |s| s.user(ui_user))?;
+
+        // explicit arguments win over the last session
+        let last_session = self.session.as_ref().and_then(SessionKey::load);
+        let room_id = match (self.config.shard.as_ref(), self.config.room.as_ref()) {
+            (shard, Some(location)) => location.room_id(shard),
+            (Some(shard), None) => {
+                let room_name = self.client.shard_start_room(shard)?.await?.room_name;
+                let room_name = RoomName::new(&room_name).map_err(|e| e.into_owned())?;
+                RoomId::new(Some(shard.clone()), room_name)
+            }
+            (None, None) => match &last_session {
+                Some(session) => session.room_id.clone(),
+                None => {
+                    let start_room = self.client.world_start_room()?.await?;
+                    let room_name =
+                        RoomName::new(&start_room.room_name).map_err(|e| e.into_owned())?;
+                    RoomId::new(start_room.shard, room_name)
+                }
+            },
+        };
+        if let Some(session) = last_session {
+            let cursor_given = self.config.room.as_ref().map_or(false, |l| l.pos.is_some());
+            let restore_cursor = session.room_id == room_id && !cursor_given;
+            ui::async_update(&self.ui, move |s| s.restore_session(session, restore_cursor))?;
+        }
 
-            let mut conn = Connected { s, sink, stream };
-            debug!("stage 1 handing off");
-            conn.run().await?;
-            debug!("stage 2 ended, stage 1 reconnecting");
-            // recapture state
-            s = conn.s;
-            cmd_recv = conn.stream.into_inner().1;
+        debug!("starting at room {}", room_id);
 
-            s.update_ui(|s| s.conn_state(ConnectionState::Disconnected))?;
+        let terrain =
+            request_terrain(&self.client, &self.http, self.recorder.as_mut(), &room_id).await?;
+        if let Some(recorder) = &mut self.recorder {
+            recorder.change_room(&room_id);
         }
+
+        debug!("successfully authenticated as {}", user.username);
+
+        Ok((user, room_id, terrain))
     }
 }
 
 impl ConnIndepState {
+    /// Opens the websocket, then authenticates and subscribes to the current room and console.
+    async fn connect(
+        &mut self,
+        ws_url: &str,
+    ) -> Result<
+        (
+            impl Sink<OwnedMessage, Error = Error> + Unpin,
+            impl Stream<Item = Result<Either<OwnedMessage, Command>, Error>> + Unpin,
+        ),
+        Error,
+    > {
+        use screeps_api::websocket::commands;
+
+        self.authenticated = false;
+
+        let (conn, _) = ClientBuilder::from_url(&ws_url.parse().unwrap())
+            .async_connect(None)
+            .compat()
+            .await?;
+
+        let (sink, stream) = conn.split();
+        let mut sink = sink.sink_compat().sink_map_err(Error::from);
+        let stream = stream.compat().map_err(Error::from);
+
+        // If we didn't have this, then the loop over this stream would just be waiting for commands
+        // after the network stream stops. This makes sure that if the network stream is disconnected,
+        // then we immediately get a 'Reconnect' message after that.
+        let stream = stream
+            .map(|res| res.map(Either::Left))
+            .chain(stream::once(future::ok(Either::Right(Command::Reconnect))));
+
+        self.update_ui(|s| s.conn_state(ConnectionState::Authenticating))?;
+
+        sink.send(OwnedMessage::Text(commands::authenticate(
+            &self
+                .tokens
+                .get()
+                .ok_or_else(|| fatal("no token available"))?,
+        )))
+        .await?;
+        self.rooms.reset();
+        let mut frames = self
+            .rooms
+            .subscribed()
+            .iter()
+            .map(|id| commands::subscribe(&Channel::room_detail(id.room_name, id.shard.as_ref())))
+            .collect::<Vec<_>>();
+        let map_rooms = self.map_rooms.iter().filter(|id| !self.owned_rooms.contains(id));
+        frames.extend(self.owned_rooms.iter().chain(map_rooms).map(|id| {
+            commands::subscribe(&Channel::room_map_view(id.room_name, id.shard.as_ref()))
+        }));
+        frames.push(commands::subscribe(&Channel::user_console(
+            &self.user.user_id,
+        )));
+        for frame in frames {
+            self.record_sent(&frame);
+            sink.send(OwnedMessage::Text(frame)).await?;
+        }
+
+        Ok((sink, stream))
+    }
+
+    /// Fetches the user's owned and reserved rooms on every shard, with their controller levels.
+    async fn fetch_owned_rooms(&mut self) -> Result<(), Error> {
+        let mut url = self.client.url.join("user/rooms")?;
+        url.query_pairs_mut().append_pair("id", &self.user.user_id);
+        let data = http::get_json_authenticated(&self.http, url.as_str(), &self.tokens).await?;
+        let mut rooms = owned_rooms_from_json(&data);
+
+        let mut shards = rooms
+            .iter()
+            .map(|r| r.room_id.shard.clone())
+            .collect::<Vec<_>>();
+        shards.sort();
+        shards.dedup();
+        for shard in shards {
+            let names = rooms
+                .iter()
+                .filter(|r| r.room_id.shard == shard)
+                .map(|r| r.room_id.room_name.to_string())
+                .collect();
+            let stats = match self.fetch_map_stats(shard.as_ref(), names).await {
+                Ok(stats) => stats,
+                Err(e) => {
+                    // the rooms are still worth listing without their levels
+                    warn!("fetching map stats of owned rooms failed: {}", e);
+                    continue;
+                }
+            };
+            for room in rooms.iter_mut().filter(|r| r.room_id.shard == shard) {
+                room.level = stats.rooms.get(&room.room_id.room_name).map(|r| r.level);
+            }
+        }
+
+        debug!("found {} owned and reserved rooms", rooms.len());
+        self.owned_rooms = rooms.iter().map(|r| r.room_id.clone()).collect();
+        self.update_ui(|s| s.owned_rooms(rooms))
+    }
+
+    async fn fetch_map_stats(
+        &self,
+        shard: Option<&String>,
+        room_names: Vec<String>,
+    ) -> Result<MapStats, Error> {
+        let url = self.client.url.join("game/map-stats")?;
+        let mut body = serde_json::json!({ "rooms": room_names, "statName": "owner0" });
+        if let Some(shard) = shard {
+            body["shard"] = shard.clone().into();
+        }
+        let data = http::post_json(&self.http, url.as_str(), &self.tokens, &body).await?;
+        Ok(MapStats::from_json(&data))
+    }
+
+    fn record_sent(&mut self, frame: &str) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.sent(frame);
+        }
+    }
+
     pub fn update_ui<F: FnOnce(&mut CursiveStatePair) + Send + 'static>(
         &self,
         func: F,
@@ -248,6 +641,9 @@ where
         while let Some(msg) = self.stream.try_next().await? {
             match msg {
                 Either::Left(OwnedMessage::Text(string)) => {
+                    if let Some(recorder) = &mut self.s.recorder {
+                        recorder.received(&string);
+                    }
                     let data = SockjsMessage::parse(&string)
                         .with_ctx(|_| format!("parsing sockjs message {:?}", string))?;
 
@@ -287,9 +683,27 @@ where
                         Command::ChangeShard(shard_name) => {
                             self.change_shard(shard_name).await?;
                         }
+                        Command::SetNeighborhood(enabled) => {
+                            self.s.rooms.set_neighborhood(enabled);
+                            let current = self.s.rooms.current().clone();
+                            self.change_room(current).await?;
+                        }
+                        Command::OpenMap(rooms) => {
+                            self.open_map(rooms).await?;
+                        }
+                        Command::CloseMap => {
+                            self.close_map().await?;
+                        }
                         Command::FetchShardNames => {
                             self.fetch_shard_names().await?;
                         }
+                        Command::ExecuteConsole(expression) => {
+                            self.execute_console(expression).await?;
+                        }
+                        Command::PlaybackTogglePause
+                        | Command::PlaybackStep
+                        | Command::PlaybackFaster
+                        | Command::PlaybackSlower => {}
                     }
                 }
             }
@@ -299,37 +713,55 @@ where
     }
 
     async fn change_room(&mut self, room_id: RoomId) -> Result<(), Error> {
-        let terrain = self
-            .s
-            .client
-            .room_terrain(room_id.shard.as_ref(), room_id.room_name.to_string())
-            .await
-            .with_ctx(|_| format!("fetching {} terrain", room_id))?;
-
-        let old_room_id = self.s.room_id.clone();
+        self.fetch_terrain(&room_id).await?;
+        if let Some(recorder) = &mut self.s.recorder {
+            recorder.change_room(&room_id);
+        }
+        for neighbor in self.s.rooms.neighbors_of(&room_id) {
+            // rooms past the edge of the world don't exist
+            if let Err(e) = self.fetch_terrain(&neighbor).await {
+                warn!("skipping neighbor {}: {}", neighbor, e);
+            }
+        }
 
-        info!("changing from {} to {}", old_room_id, room_id);
+        info!("changing from {} to {}", self.s.rooms.current(), room_id);
 
-        self.sink
-            .send(OwnedMessage::Text(unsubscribe(&Channel::room_detail(
-                old_room_id.room_name,
-                old_room_id.shard.as_ref(),
-            ))))
+        let changes = self.s.rooms.view(room_id)?;
+        for old in changes.unsubscribe {
+            self.send_recorded(unsubscribe(&Channel::room_detail(
+                old.room_name,
+                old.shard.as_ref(),
+            )))
             .await?;
-
-        self.sink
-            .send(OwnedMessage::Text(subscribe(&Channel::room_detail(
-                room_id.room_name,
-                room_id.shard.as_ref(),
-            ))))
+        }
+        for new in changes.subscribe {
+            self.send_recorded(subscribe(&Channel::room_detail(
+                new.room_name,
+                new.shard.as_ref(),
+            )))
             .await?;
-
-        self.s.room_id = room_id.clone();
-        self.s.room = Room::new(room_id, terrain);
+        }
 
         Ok(())
     }
 
+    /// Gets a room's terrain from the cache, or from the server if we haven't seen it yet.
+    async fn fetch_terrain(&mut self, room_id: &RoomId) -> Result<Terrain, Error> {
+        if let Some(terrain) = self.s.rooms.terrain(room_id) {
+            return Ok(terrain.clone());
+        }
+        let s = &mut self.s;
+        let terrain = request_terrain(&s.client, &s.http, s.recorder.as_mut(), room_id).await?;
+        self.s.rooms.add_terrain(room_id.clone(), terrain.clone());
+        Ok(terrain)
+    }
+
+    /// Sends a text frame, also writing it to the recording if there is one.
+    async fn send_recorded(&mut self, frame: String) -> Result<(), Error> {
+        self.s.record_sent(&frame);
+        self.sink.send(OwnedMessage::Text(frame)).await
+    }
+
     async fn change_shard(&mut self, shard_name: String) -> Result<(), Error> {
         let room_name = self
             .s
@@ -343,6 +775,47 @@ where
             .await
     }
 
+    async fn open_map(&mut self, rooms: Vec<RoomId>) -> Result<(), Error> {
+        self.close_map().await?;
+
+        let names = rooms
+            .iter()
+            .map(|id| id.room_name.to_string())
+            .collect();
+        let shard = rooms.first().and_then(|id| id.shard.as_ref());
+        // the map is still useful without stats, since map views come through the websocket
+        match self.s.fetch_map_stats(shard, names).await {
+            Ok(stats) => self.s.update_ui(|s| s.map_stats(stats))?,
+            Err(e) => warn!("fetching map stats failed: {}", e),
+        }
+
+        // owned rooms are already subscribed to
+        let new_rooms = rooms.iter().filter(|id| !self.s.owned_rooms.contains(id));
+        for room_id in new_rooms.cloned().collect::<Vec<_>>() {
+            self.send_recorded(subscribe(&Channel::room_map_view(
+                room_id.room_name,
+                room_id.shard.as_ref(),
+            )))
+            .await?;
+        }
+        self.s.map_rooms = rooms;
+        Ok(())
+    }
+
+    async fn close_map(&mut self) -> Result<(), Error> {
+        for room_id in mem::take(&mut self.s.map_rooms) {
+            if self.s.owned_rooms.contains(&room_id) {
+                continue;
+            }
+            self.send_recorded(unsubscribe(&Channel::room_map_view(
+                room_id.room_name,
+                room_id.shard.as_ref(),
+            )))
+            .await?;
+        }
+        Ok(())
+    }
+
     async fn fetch_shard_names(&mut self) -> Result<(), Error> {
         let res = self
             .s
@@ -356,65 +829,51 @@ where
         Ok(())
     }
 
+    async fn execute_console(&mut self, expression: String) -> Result<(), Error> {
+        let url = self.s.client.url.join("user/console")?;
+        let mut body = serde_json::json!({ "expression": expression });
+        if let Some(shard) = &self.s.rooms.current().shard {
+            body["shard"] = shard.clone().into();
+        }
+
+        // results come back through the user console channel, so we only need to report failures
+        if let Err(e) = http::post_json(&self.s.http, url.as_str(), &self.s.tokens, &body).await {
+            warn!(
+                "executing console expression {:?} failed: {}",
+                expression, e
+            );
+            let message = format!("failed to execute expression: {}", e);
+            self.s.update_ui(|s| s.console_error(message))?;
+        }
+        Ok(())
+    }
+
     async fn handle_message<'a>(&'a mut self, msg: ScreepsMessage<'a>) -> Result<(), Error> {
         match msg {
-            ScreepsMessage::AuthFailed => return Err("authentication failed".into()),
+            ScreepsMessage::AuthFailed => {
+                let username = match &self.s.config.username {
+                    Some(username) if !self.s.signed_in_again => username.clone(),
+                    _ => return Err(fatal("authentication failed")),
+                };
+                info!("token rejected, signing in again as {}", username);
+                let url = self.s.client.url.join("auth/signin")?;
+                let password = self.s.config.password.clone().unwrap_or_default();
+                let token = sign_in(&self.s.http, url.as_str(), &username, &password)
+                    .await
+                    .map_err(fatal)?;
+                self.s.tokens.set(token);
+                self.s.signed_in_again = true;
+                return Err("token rejected, reconnecting with a new one".into());
+            }
             ScreepsMessage::AuthOk { new_token } => {
+                self.s.authenticated = true;
+                self.s.signed_in_again = false;
                 self.s
                     .update_ui(|s| s.conn_state(ConnectionState::Connected))?;
                 self.s.tokens.set(new_token);
             }
-            ScreepsMessage::ChannelUpdate {
-                update:
-                    ChannelUpdate::RoomDetail {
-                        room_name,
-                        shard_name,
-                        update,
-                    },
-            } => {
-                let update_id = RoomId::new(shard_name, room_name);
-                if update_id != self.s.room_id {
-                    warn!(
-                        "received update for wrong room: expected {}, found {}",
-                        self.s.room_id, update_id
-                    );
-                    return Ok(());
-                }
-
-                self.s
-                    .room
-                    .update(update)
-                    .with_ctx(|_| format!("handling room update for {}", update_id))?;
-                debug!("updated room {}: {:?}", self.s.room_id, self.s.room);
-                let visual = self.s.room.visualize();
-                self.s.update_ui(|s| s.room(visual))?;
-            }
-            ScreepsMessage::ChannelUpdate {
-                update: ChannelUpdate::UserConsole { user_id, update },
-            } => {
-                if user_id != self.s.user.user_id {
-                    warn!(
-                        "received console update for wrong user: expected {}, found {}",
-                        self.s.user.user_id, user_id
-                    );
-                }
-                self.s.update_ui(|s| s.console_update(update))?;
-            }
-            ScreepsMessage::ServerProtocol { protocol } => {
-                debug!("server protocol: {}", protocol);
-            }
-            ScreepsMessage::ServerTime { time } => {
-                debug!("server time: {}", time);
-            }
-            ScreepsMessage::ServerPackage { package } => {
-                debug!("server package: {}", package);
-            }
-            ScreepsMessage::Other(other) => {
-                warn!("Unkown type of screeps message: {}", other);
-            }
-            other => debug!("ignoring {:?}", other),
+            other => self.s.rooms.handle_message(other)?,
         }
-        debug!("handled message successfully");
 
         Ok(())
     }
@@ -0,0 +1,68 @@
+//! Exponential backoff between reconnection attempts.
+use std::{
+    cmp,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use cursive::CbSink;
+
+use super::Error;
+use crate::{room::ConnectionState, ui};
+
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(120);
+
+#[derive(Clone, Debug, Default)]
+pub struct Backoff {
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resets the delay after a connection succeeded.
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    /// Delay before the next attempt: doubles each attempt up to `MAX_DELAY`, with the upper
+    /// half randomized so that many clients don't all reconnect at the same time.
+    pub fn next_delay(&mut self) -> Duration {
+        let max = BASE_DELAY
+            .checked_mul(1 << cmp::min(self.attempt, 16))
+            .map_or(MAX_DELAY, |d| cmp::min(d, MAX_DELAY));
+        self.attempt = self.attempt.saturating_add(1);
+
+        max / 2 + max.mul_f64(jitter() / 2.0)
+    }
+
+    /// Waits for the next delay, counting down the seconds remaining in the UI.
+    pub async fn wait(&mut self, ui: &CbSink) -> Result<(), Error> {
+        let deadline = Instant::now() + self.next_delay();
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.as_millis() == 0 {
+                return Ok(());
+            }
+            let secs = (remaining.as_millis() as u64 + 999) / 1000;
+            ui::async_update(ui, move |s| {
+                s.conn_state(ConnectionState::Reconnecting(secs))
+            })?;
+            // sleep until the displayed number of seconds changes
+            tokio::time::delay_for(remaining - Duration::from_secs(secs - 1)).await;
+        }
+    }
+}
+
+/// A cheap pseudo-random number in `[0, 1)`.
+///
+/// Only used to spread out reconnects, so the clock's sub-second noise is random enough.
+fn jitter() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    f64::from(nanos) / 1_000_000_000.0
+}
@@ -0,0 +1,276 @@
+//! Playback of the server's room history archive, for reviewing ticks nobody was watching.
+use std::time::Duration;
+
+use cursive::CbSink;
+use err_ctx::ResultExt;
+use hyper::client::HttpConnector;
+use hyper_tls::HttpsConnector;
+use log::{debug, info, warn};
+use screeps_api::{websocket::RoomUpdate, Api};
+use serde_json::json;
+
+use super::{http, playback::Playback, rooms::RoomManager, Error};
+use crate::{
+    config::{Config, TickRange},
+    room::{RoomId, Terrain},
+    ui,
+};
+
+/// Ticks stored in each history file. Each file starts with the whole room, followed by diffs.
+const CHUNK_TICKS: u32 = 20;
+
+/// Playback time between ticks at normal speed.
+const TICK_DURATION: Duration = Duration::from_secs(1);
+
+pub fn run(config: &Config, ticks: TickRange, ui: CbSink) -> Result<(), Error> {
+    let location = config.room.as_ref().ok_or("--history requires --room")?;
+    let room_id = location.room_id(config.shard.as_ref());
+
+    let hyper = hyper::Client::builder().build::<_, hyper::Body>(HttpsConnector::new());
+    let mut client = Api::new(hyper.clone());
+    if let Some(u) = &config.server {
+        client.set_url(u)?;
+    }
+
+    let server = client.url.to_string();
+    ui::async_update(&ui, |s| s.load_bookmarks(server))?;
+    let server = format!("history of {}", client.url);
+    ui::async_update(&ui, |s| s.server(server))?;
+
+    let mut runtime = tokio::runtime::Runtime::new()?;
+
+    let terrain = runtime
+        .block_on(client.room_terrain(room_id.shard.as_ref(), room_id.room_name.to_string()))
+        .with_ctx(|_| format!("fetching {} terrain", room_id))?;
+    let terrain = Terrain::from(&terrain);
+
+    info!("playing history of {} from {}", room_id, ticks);
+
+    let mut rooms = RoomManager::new(ui.clone(), String::new(), room_id.clone(), terrain);
+    let mut playback = Playback::new(ui)?;
+
+    let mut chunk_start = ticks.start - ticks.start % CHUNK_TICKS;
+    while chunk_start <= ticks.end {
+        let chunk = match runtime.block_on(fetch_chunk(&client, &hyper, &room_id, chunk_start))? {
+            Some(chunk) => chunk,
+            None => {
+                chunk_start += CHUNK_TICKS;
+                continue;
+            }
+        };
+
+        for tick in chunk_start..chunk_start + CHUNK_TICKS {
+            if tick > ticks.end {
+                break;
+            }
+            let known_ids = rooms
+                .room(&room_id)
+                .into_iter()
+                .flat_map(|room| room.object_ids());
+            let objects = tick_objects(&chunk, chunk_start, tick, known_ids);
+            debug!("history tick {}: {} objects changed", tick, objects.len());
+
+            rooms.apply_update(room_id.clone(), room_update(tick, objects)?)?;
+            // ticks before the start only bring the room up to date
+            if tick >= ticks.start {
+                playback.wait(TICK_DURATION)?;
+            }
+        }
+        chunk_start += CHUNK_TICKS;
+    }
+
+    info!("history finished");
+    playback.finished()
+}
+
+/// Path of the history file starting at `tick`, relative to the API url.
+fn chunk_path(room_id: &RoomId, tick: u32) -> String {
+    match &room_id.shard {
+        Some(shard) => format!(
+            "../room-history/{}/{}/{}.json",
+            shard, room_id.room_name, tick
+        ),
+        None => format!("../room-history/{}/{}.json", room_id.room_name, tick),
+    }
+}
+
+/// Fetches the history file starting at `chunk_start`, or `None` if the archive doesn't have it.
+async fn fetch_chunk(
+    client: &Api<HttpsConnector<HttpConnector>>,
+    hyper: &http::HttpClient,
+    room_id: &RoomId,
+    chunk_start: u32,
+) -> Result<Option<serde_json::Value>, Error> {
+    let url = client.url.join(&chunk_path(room_id, chunk_start))?;
+    match http::get_json(hyper, url.as_str()).await {
+        Ok(chunk) => Ok(Some(chunk)),
+        Err(e) => {
+            // the archive has gaps; the next chunk starts with the whole room again
+            warn!("skipping history chunk at {}: {}", chunk_start, e);
+            Ok(None)
+        }
+    }
+}
+
+/// Objects changed at `tick`, from the chunk starting at `chunk_start`.
+///
+/// The chunk's base tick holds the whole room, so any of `known_ids` missing from it are gone.
+fn tick_objects<'a>(
+    chunk: &serde_json::Value,
+    chunk_start: u32,
+    tick: u32,
+    known_ids: impl IntoIterator<Item = &'a str>,
+) -> serde_json::Map<String, serde_json::Value> {
+    let mut objects = match &chunk["ticks"][tick.to_string()] {
+        serde_json::Value::Object(objects) => objects.clone(),
+        _ => serde_json::Map::new(),
+    };
+    let base = chunk["base"].as_u64().map_or(chunk_start, |b| b as u32);
+    if tick == base {
+        for id in known_ids {
+            if !objects.contains_key(id) {
+                objects.insert(id.to_owned(), serde_json::Value::Null);
+            }
+        }
+    }
+    objects
+}
+
+fn room_update(
+    tick: u32,
+    objects: serde_json::Map<String, serde_json::Value>,
+) -> Result<RoomUpdate, Error> {
+    let update = json!({
+        "objects": objects,
+        "gameTime": tick,
+        "info": { "mode": "world" },
+    });
+    Ok(serde_json::from_value(update).with_ctx(|_| format!("parsing history tick {}", tick))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, convert::Infallible, net::SocketAddr, sync::Arc};
+
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Response, Server, StatusCode,
+    };
+    use screeps_api::RoomName;
+
+    use super::*;
+
+    /// Serves `files` on a local port, with a 404 for anything else. Must be called on a runtime.
+    fn serve(files: &[(&str, String)]) -> SocketAddr {
+        let files = Arc::new(
+            files
+                .iter()
+                .map(|(path, body)| (path.to_string(), body.clone()))
+                .collect::<HashMap<_, _>>(),
+        );
+        let make_service = make_service_fn(move |_| {
+            let files = files.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let response = match files.get(request.uri().path()) {
+                        Some(body) => Response::new(Body::from(body.clone())),
+                        None => Response::builder()
+                            .status(StatusCode::NOT_FOUND)
+                            .body(Body::empty())
+                            .expect("building 404 response"),
+                    };
+                    async move { Ok::<_, Infallible>(response) }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(async move {
+            server.await.expect("running history server");
+        });
+        addr
+    }
+
+    fn clients(addr: SocketAddr) -> (Api<HttpsConnector<HttpConnector>>, http::HttpClient) {
+        let hyper = hyper::Client::builder().build::<_, hyper::Body>(HttpsConnector::new());
+        let mut client = Api::new(hyper.clone());
+        client
+            .set_url(&format!("http://{}/api/", addr))
+            .expect("setting server url");
+        (client, hyper)
+    }
+
+    fn room_id(shard: Option<&str>) -> RoomId {
+        RoomId::new(
+            shard.map(ToOwned::to_owned),
+            RoomName::new("W1N1").expect("parsing room name"),
+        )
+    }
+
+    #[test]
+    fn chunk_path_with_and_without_shard() {
+        assert_eq!(
+            chunk_path(&room_id(Some("shard0")), 20),
+            "../room-history/shard0/W1N1/20.json"
+        );
+        assert_eq!(
+            chunk_path(&room_id(None), 40),
+            "../room-history/W1N1/40.json"
+        );
+    }
+
+    #[test]
+    fn fetches_chunks_and_skips_missing_ones() {
+        let room_id = room_id(Some("shard0"));
+        let chunk = json!({ "base": 0, "ticks": { "0": { "a": { "type": "source" } } } });
+        let mut runtime = tokio::runtime::Runtime::new().expect("starting runtime");
+        runtime.block_on(async {
+            let addr = serve(&[
+                ("/room-history/shard0/W1N1/0.json", chunk.to_string()),
+                ("/room-history/shard0/W1N1/40.json", "not json".to_owned()),
+            ]);
+            let (client, hyper) = clients(addr);
+
+            let fetched = fetch_chunk(&client, &hyper, &room_id, 0).await;
+            assert_eq!(fetched.expect("fetching chunk 0"), Some(chunk));
+            let missing = fetch_chunk(&client, &hyper, &room_id, 20).await;
+            assert_eq!(missing.expect("fetching chunk 20"), None);
+            let broken = fetch_chunk(&client, &hyper, &room_id, 40).await;
+            assert_eq!(broken.expect("fetching chunk 40"), None);
+        });
+    }
+
+    #[test]
+    fn base_tick_removes_objects_that_disappeared() {
+        let room_id = room_id(None);
+        let chunk = json!({
+            "base": 20,
+            "ticks": {
+                "20": { "a": { "type": "source", "energy": 3000 } },
+                "21": { "a": { "energy": 2990 } },
+            },
+        });
+        let mut runtime = tokio::runtime::Runtime::new().expect("starting runtime");
+        let chunk = runtime.block_on(async {
+            let addr = serve(&[("/room-history/W1N1/20.json", chunk.to_string())]);
+            let (client, hyper) = clients(addr);
+            fetch_chunk(&client, &hyper, &room_id, 20)
+                .await
+                .expect("fetching chunk 20")
+                .expect("chunk 20 exists")
+        });
+
+        let base = tick_objects(&chunk, 20, 20, vec!["a", "b"]);
+        assert_eq!(base["a"], json!({ "type": "source", "energy": 3000 }));
+        assert_eq!(base["b"], serde_json::Value::Null);
+        assert_eq!(base.len(), 2);
+
+        // later ticks are diffs, so objects missing from them are unchanged
+        let diff = tick_objects(&chunk, 20, 21, vec!["a", "b"]);
+        assert_eq!(diff["a"], json!({ "energy": 2990 }));
+        assert_eq!(diff.len(), 1);
+
+        // a gap in the chunk is a tick where nothing changed
+        assert!(tick_objects(&chunk, 20, 22, vec!["a", "b"]).is_empty());
+    }
+}
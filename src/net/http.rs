@@ -0,0 +1,128 @@
+//! Raw HTTP requests for server endpoints which screeps_api doesn't wrap.
+use std::fmt;
+
+use bytes::Bytes;
+use err_ctx::ResultExt;
+use hyper::{
+    client::HttpConnector,
+    header::{HeaderValue, CONTENT_TYPE},
+    Body, Request, Response, StatusCode,
+};
+use hyper_tls::HttpsConnector;
+use screeps_api::TokenStorage;
+
+use super::Error;
+
+pub type HttpClient = hyper::Client<HttpsConnector<HttpConnector>>;
+
+/// A response with an unsuccessful status, so that rejected requests can be told apart from
+/// network errors.
+#[derive(Debug)]
+pub struct StatusError {
+    url: String,
+    pub status: StatusCode,
+    body: String,
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} returned {}: {}", self.url, self.status, self.body)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+/// Sends an authenticated POST request with a JSON body, returning the JSON response.
+///
+/// Token renewals sent back by the server are stored in `tokens`.
+pub async fn post_json(
+    client: &HttpClient,
+    url: &str,
+    tokens: &TokenStorage,
+    body: &serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let request = Request::post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(body)?))?;
+    send_authenticated(client, url, tokens, request).await
+}
+
+/// Sends an authenticated GET request, returning the JSON response.
+///
+/// Token renewals sent back by the server are stored in `tokens`.
+pub async fn get_json_authenticated(
+    client: &HttpClient,
+    url: &str,
+    tokens: &TokenStorage,
+) -> Result<serde_json::Value, Error> {
+    let request = Request::get(url).body(Body::empty())?;
+    send_authenticated(client, url, tokens, request).await
+}
+
+async fn send_authenticated(
+    client: &HttpClient,
+    url: &str,
+    tokens: &TokenStorage,
+    mut request: Request<Body>,
+) -> Result<serde_json::Value, Error> {
+    let token = tokens.get().ok_or("no token available")?;
+    let token = HeaderValue::from_bytes(&token)?;
+    request.headers_mut().insert("X-Token", token.clone());
+    request.headers_mut().insert("X-Username", token);
+
+    let response = client
+        .request(request)
+        .await
+        .with_ctx(|_| format!("requesting {}", url))?;
+
+    if let Some(new_token) = response.headers().get("X-Token") {
+        tokens.set(Bytes::copy_from_slice(new_token.as_bytes()));
+    }
+
+    read_json(url, response).await
+}
+
+/// Sends an unauthenticated POST request with a JSON body, returning the JSON response.
+pub async fn post_json_unauthenticated(
+    client: &HttpClient,
+    url: &str,
+    body: &serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let request = Request::post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(body)?))?;
+    let response = client
+        .request(request)
+        .await
+        .with_ctx(|_| format!("requesting {}", url))?;
+
+    read_json(url, response).await
+}
+
+/// Sends an unauthenticated GET request, returning the JSON response.
+pub async fn get_json(client: &HttpClient, url: &str) -> Result<serde_json::Value, Error> {
+    let response = client
+        .get(url.parse()?)
+        .await
+        .with_ctx(|_| format!("requesting {}", url))?;
+
+    read_json(url, response).await
+}
+
+async fn read_json(url: &str, response: Response<Body>) -> Result<serde_json::Value, Error> {
+    let status = response.status();
+    let data = hyper::body::to_bytes(response.into_body())
+        .await
+        .with_ctx(|_| format!("reading response from {}", url))?;
+
+    if !status.is_success() {
+        return Err(StatusError {
+            url: url.to_owned(),
+            status,
+            body: String::from_utf8_lossy(&data).into_owned(),
+        }
+        .into());
+    }
+
+    Ok(serde_json::from_slice(&data).with_ctx(|_| format!("parsing response from {}", url))?)
+}
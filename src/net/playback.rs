@@ -0,0 +1,103 @@
+//! Play/pause, step and speed controls shared by the offline playback modes.
+use std::{
+    cmp, thread,
+    time::{Duration, Instant},
+};
+
+use cursive::CbSink;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver};
+use log::debug;
+
+use super::{Command, Error};
+use crate::{room::ConnectionState, ui};
+
+const MIN_SPEED_PERCENT: u32 = 25;
+const MAX_SPEED_PERCENT: u32 = 6400;
+
+/// How often commands are checked for while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub struct Playback {
+    ui: CbSink,
+    cmd_recv: UnboundedReceiver<Command>,
+    paused: bool,
+    speed_percent: u32,
+    /// Set when the user asked to skip straight to the next step.
+    step: bool,
+}
+
+impl Playback {
+    /// Takes over the UI's commands, and adds the playback controls to it.
+    pub fn new(ui: CbSink) -> Result<Self, Error> {
+        let (cmd_send, cmd_recv) = unbounded();
+        ui::async_update(&ui, |s| {
+            s.command_sender(cmd_send);
+            s.playback_controls();
+        })?;
+
+        let playback = Playback {
+            ui,
+            cmd_recv,
+            paused: false,
+            speed_percent: 100,
+            step: false,
+        };
+        playback.update_state()?;
+        Ok(playback)
+    }
+
+    /// Waits for `duration` of playback time, or until the user steps forward.
+    pub fn wait(&mut self, duration: Duration) -> Result<(), Error> {
+        let mut waited = Duration::from_secs(0);
+        let mut last_tick = Instant::now();
+        while waited < duration {
+            self.handle_commands()?;
+            if self.step {
+                self.step = false;
+                break;
+            }
+            let remaining = (duration - waited) * 100 / self.speed_percent;
+            thread::sleep(cmp::min(remaining, POLL_INTERVAL));
+            let now = Instant::now();
+            if !self.paused {
+                waited += (now - last_tick) * self.speed_percent / 100;
+            }
+            last_tick = now;
+        }
+        Ok(())
+    }
+
+    pub fn finished(&self) -> Result<(), Error> {
+        ui::async_update(&self.ui, |s| s.conn_state(ConnectionState::Finished))
+    }
+
+    fn handle_commands(&mut self) -> Result<(), Error> {
+        while let Ok(Some(cmd)) = self.cmd_recv.try_next() {
+            match cmd {
+                Command::PlaybackTogglePause => self.paused = !self.paused,
+                Command::PlaybackStep => self.step = true,
+                Command::PlaybackFaster => {
+                    self.speed_percent = cmp::min(self.speed_percent * 2, MAX_SPEED_PERCENT)
+                }
+                Command::PlaybackSlower => {
+                    self.speed_percent = cmp::max(self.speed_percent / 2, MIN_SPEED_PERCENT)
+                }
+                other => {
+                    debug!("ignoring command {:?} during playback", other);
+                    continue;
+                }
+            }
+            self.update_state()?;
+        }
+        Ok(())
+    }
+
+    fn update_state(&self) -> Result<(), Error> {
+        let state = if self.paused {
+            ConnectionState::Paused
+        } else {
+            ConnectionState::Playing(self.speed_percent)
+        };
+        ui::async_update(&self.ui, move |s| s.conn_state(state))
+    }
+}
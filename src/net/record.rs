@@ -0,0 +1,181 @@
+//! Recording of raw server traffic to a file, one JSON entry per line.
+//!
+//! Recordings are meant to be attached to bug reports, so the auth token is never written.
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, LineWriter, Write},
+    path::Path,
+    time::Instant,
+};
+
+use err_ctx::ResultExt;
+use log::warn;
+use screeps_api::RoomName;
+use serde_json::json;
+
+use super::Error;
+use crate::room::{RoomId, Terrain};
+
+/// Version of the recording format, written in the first entry.
+pub const FORMAT_VERSION: u32 = 1;
+
+pub struct Recorder {
+    start: Instant,
+    out: LineWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, server: &str) -> Result<Self, Error> {
+        let file =
+            File::create(path).with_ctx(|_| format!("creating recording {}", path.display()))?;
+        let mut recorder = Recorder {
+            start: Instant::now(),
+            out: LineWriter::new(file),
+        };
+        recorder.write(
+            "start",
+            json!({ "version": FORMAT_VERSION, "server": server }),
+        );
+        Ok(recorder)
+    }
+
+    /// Records a text frame received from the websocket.
+    pub fn received(&mut self, frame: &str) {
+        self.write("recv", redact_auth_token(frame).into());
+    }
+
+    /// Records a subscribe or unsubscribe frame sent to the websocket.
+    pub fn sent(&mut self, frame: &str) {
+        self.write("send", frame.into());
+    }
+
+    /// Records the `auth/me` response with only the fields a replay needs, leaving out
+    /// personal details like the user's email.
+    pub fn my_info(&mut self, response: &serde_json::Value) {
+        self.write(
+            "my_info",
+            json!({ "_id": response["_id"], "username": response["username"] }),
+        );
+    }
+
+    /// Records the raw response from `game/room-terrain`, which doesn't say which shard it's for.
+    pub fn room_terrain(&mut self, shard: Option<&String>, response: &serde_json::Value) {
+        self.write(
+            "room_terrain",
+            json!({ "shard": shard, "response": response }),
+        );
+    }
+
+    /// Records the client switching to viewing a room.
+    pub fn change_room(&mut self, room_id: &RoomId) {
+        self.write(
+            "change_room",
+            json!({ "shard": room_id.shard, "room": room_id.room_name.to_string() }),
+        );
+    }
+
+    fn write(&mut self, kind: &str, data: serde_json::Value) {
+        let entry = json!({
+            "time": self.start.elapsed().as_millis() as u64,
+            "kind": kind,
+            "data": data,
+        });
+        // a broken recording shouldn't take down the connection
+        if let Err(e) = writeln!(self.out, "{}", entry) {
+            warn!("failed to write to recording: {}", e);
+        }
+    }
+}
+
+/// A single recorded event.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    /// Milliseconds since the recording started.
+    pub time: u64,
+    pub kind: EntryKind,
+}
+
+#[derive(Clone, Debug)]
+pub enum EntryKind {
+    Start { server: String },
+    Received(String),
+    Sent(String),
+    MyInfo { user_id: String, username: String },
+    RoomTerrain { room_id: RoomId, terrain: Terrain },
+    ChangeRoom(RoomId),
+}
+
+/// Reads all entries of a recording.
+pub fn read(path: &Path) -> Result<Vec<Entry>, Error> {
+    let file = File::open(path).with_ctx(|_| format!("opening recording {}", path.display()))?;
+    let mut entries = Vec::new();
+    for (idx, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_ctx(|_| format!("reading recording {}", path.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = parse_entry(&line)
+            .with_ctx(|_| format!("parsing {} line {}", path.display(), idx + 1))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+fn parse_entry(line: &str) -> Result<Entry, Error> {
+    let value: serde_json::Value = serde_json::from_str(line)?;
+    let time = value["time"].as_u64().ok_or("missing time")?;
+    let data = &value["data"];
+    let str_field = |v: &serde_json::Value, name: &str| -> Result<String, Error> {
+        Ok(v[name]
+            .as_str()
+            .ok_or_else(|| format!("missing {}", name))?
+            .to_owned())
+    };
+    let kind = match value["kind"].as_str().ok_or("missing kind")? {
+        "start" => {
+            let version = data["version"].as_u64().ok_or("missing version")?;
+            if version != u64::from(FORMAT_VERSION) {
+                return Err(format!("unsupported recording version {}", version).into());
+            }
+            EntryKind::Start {
+                server: str_field(data, "server")?,
+            }
+        }
+        "recv" => EntryKind::Received(data.as_str().ok_or("expected frame")?.to_owned()),
+        "send" => EntryKind::Sent(data.as_str().ok_or("expected frame")?.to_owned()),
+        "my_info" => EntryKind::MyInfo {
+            user_id: str_field(data, "_id")?,
+            username: str_field(data, "username")?,
+        },
+        "room_terrain" => {
+            let terrain = Terrain::from_response(&data["response"])?;
+            let shard = data["shard"].as_str().map(ToOwned::to_owned);
+            EntryKind::RoomTerrain {
+                room_id: RoomId::new(shard, terrain.room_name()),
+                terrain,
+            }
+        }
+        "change_room" => {
+            let room_name = RoomName::new(&str_field(data, "room")?).map_err(|e| e.into_owned())?;
+            let shard = data["shard"].as_str().map(ToOwned::to_owned);
+            EntryKind::ChangeRoom(RoomId::new(shard, room_name))
+        }
+        other => return Err(format!("unknown entry kind {:?}", other).into()),
+    };
+    Ok(Entry { time, kind })
+}
+
+/// Replaces the renewed token in `auth ok <token>` messages.
+fn redact_auth_token(frame: &str) -> String {
+    const AUTH_OK: &str = "auth ok ";
+    match frame.find(AUTH_OK) {
+        Some(start) => {
+            let token_start = start + AUTH_OK.len();
+            let token_end = frame[token_start..]
+                .find(|c: char| c == '"' || c == '\\')
+                .map_or(frame.len(), |len| token_start + len);
+            format!("{}redacted{}", &frame[..token_start], &frame[token_end..])
+        }
+        None => frame.to_owned(),
+    }
+}
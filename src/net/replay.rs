@@ -0,0 +1,133 @@
+//! Offline replay of a session recorded with `--record`.
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use cursive::CbSink;
+use log::{debug, error, info, warn};
+use screeps_api::websocket::{ScreepsMessage, SockjsMessage};
+
+use super::{
+    playback::Playback,
+    record::{self, EntryKind},
+    rooms::RoomManager,
+    Error,
+};
+use crate::{
+    room::{RoomId, Terrain},
+    ui,
+};
+
+pub fn run(path: &Path, ui: CbSink) -> Result<(), Error> {
+    let entries = record::read(path)?;
+    info!(
+        "replaying {} entries from {}",
+        entries.len(),
+        path.display()
+    );
+
+    let mut replay = Replay {
+        playback: Playback::new(ui.clone())?,
+        ui,
+        user_id: String::new(),
+        terrain: HashMap::new(),
+        rooms: None,
+    };
+
+    let mut position = Duration::from_secs(0);
+    for entry in entries {
+        let entry_time = Duration::from_millis(entry.time);
+        if entry_time > position {
+            replay.playback.wait(entry_time - position)?;
+            position = entry_time;
+        }
+        replay.apply(entry.kind)?;
+    }
+
+    info!("replay finished");
+    replay.playback.finished()
+}
+
+struct Replay {
+    ui: CbSink,
+    playback: Playback,
+    user_id: String,
+    /// Terrain replayed before the first room change, which creates `rooms`.
+    terrain: HashMap<RoomId, Terrain>,
+    /// Created once the first room is viewed.
+    rooms: Option<RoomManager>,
+}
+
+impl Replay {
+    fn apply(&mut self, entry: EntryKind) -> Result<(), Error> {
+        match entry {
+            EntryKind::Start { server } => {
+                let server = format!("replay of {}", server);
+                ui::async_update(&self.ui, |s| s.server(server))?;
+            }
+            EntryKind::MyInfo { user_id, username } => {
+                self.user_id = user_id;
+                ui::async_update(&self.ui, |s| s.username(username))?;
+            }
+            EntryKind::RoomTerrain { room_id, terrain } => match &mut self.rooms {
+                Some(rooms) => rooms.add_terrain(room_id, terrain),
+                None => {
+                    self.terrain.insert(room_id, terrain);
+                }
+            },
+            EntryKind::ChangeRoom(room_id) => {
+                debug!("replay changing to {}", room_id);
+                match &mut self.rooms {
+                    Some(rooms) => {
+                        rooms.view(room_id)?;
+                    }
+                    None => {
+                        let terrain = self
+                            .terrain
+                            .remove(&room_id)
+                            .ok_or_else(|| format!("no terrain recorded for {}", room_id))?;
+                        let mut rooms = RoomManager::new(
+                            self.ui.clone(),
+                            self.user_id.clone(),
+                            room_id,
+                            terrain,
+                        );
+                        for (room_id, terrain) in self.terrain.drain() {
+                            rooms.add_terrain(room_id, terrain);
+                        }
+                        self.rooms = Some(rooms);
+                    }
+                }
+            }
+            EntryKind::Sent(frame) => debug!("recording sent {}", frame),
+            EntryKind::Received(frame) => {
+                let rooms = match &mut self.rooms {
+                    Some(rooms) => rooms,
+                    None => {
+                        warn!("skipping frame received before any room: {}", frame);
+                        return Ok(());
+                    }
+                };
+                let messages = match SockjsMessage::parse(&frame) {
+                    Ok(SockjsMessage::Message(inner)) => vec![inner],
+                    Ok(SockjsMessage::Messages(inners)) => inners,
+                    Ok(_) => return Ok(()),
+                    Err(e) => {
+                        warn!("skipping unparseable frame {:?}: {}", frame, e);
+                        return Ok(());
+                    }
+                };
+                for msg in messages {
+                    if let ScreepsMessage::AuthOk { .. } | ScreepsMessage::AuthFailed = msg {
+                        continue;
+                    }
+                    // replays are for debugging, so keep going past failures
+                    if let Err(e) = rooms.handle_message(msg) {
+                        error!("failed to handle replayed message: {}", e);
+                        let message = format!("replay error (see log): {}", e);
+                        ui::async_update(&self.ui, |s| s.console_error(message))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+//! Subscriptions to several rooms at once, with the state of each kept up to date.
+use std::collections::HashMap;
+
+use cursive::CbSink;
+use err_ctx::ResultExt;
+use log::{debug, warn};
+use screeps_api::websocket::{ChannelUpdate, RoomUpdate, ScreepsMessage};
+
+use super::Error;
+use crate::{
+    room::{Room, RoomId, Terrain},
+    ui,
+};
+
+/// Most rooms subscribed to at once. The least recently viewed room is dropped past this.
+///
+/// This leaves room for a few recently viewed rooms on top of a whole neighborhood.
+const MAX_SUBSCRIBED_ROOMS: usize = 12;
+
+/// Channel changes needed after viewing a room.
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    pub subscribe: Vec<RoomId>,
+    pub unsubscribe: Vec<RoomId>,
+}
+
+/// Applies server messages to every subscribed room, and shows the viewed one in the UI.
+///
+/// This is separate from the connection so that replays can drive it without a server.
+pub struct RoomManager {
+    ui: CbSink,
+    user_id: String,
+    /// The room shown in the UI.
+    current: RoomId,
+    rooms: HashMap<RoomId, Room>,
+    terrain: HashMap<RoomId, Terrain>,
+    /// Subscribed rooms, least recently viewed first.
+    subscribed: Vec<RoomId>,
+    /// Whether the viewed room's neighbors are subscribed to and shown too.
+    neighborhood: bool,
+}
+
+impl RoomManager {
+    pub fn new(ui: CbSink, user_id: String, room_id: RoomId, terrain: Terrain) -> Self {
+        let mut manager = RoomManager {
+            ui,
+            user_id,
+            current: room_id.clone(),
+            rooms: HashMap::new(),
+            terrain: HashMap::new(),
+            subscribed: Vec::new(),
+            neighborhood: false,
+        };
+        manager.rooms.insert(room_id.clone(), Room::new(room_id.clone(), terrain.clone()));
+        manager.terrain.insert(room_id.clone(), terrain);
+        manager.subscribed.push(room_id);
+        manager
+    }
+
+    /// The room shown in the UI.
+    pub fn current(&self) -> &RoomId {
+        &self.current
+    }
+
+    pub fn room(&self, room_id: &RoomId) -> Option<&Room> {
+        self.rooms.get(room_id)
+    }
+
+    /// Terrain of a room we've seen before.
+    pub fn terrain(&self, room_id: &RoomId) -> Option<&Terrain> {
+        self.terrain.get(room_id)
+    }
+
+    pub fn add_terrain(&mut self, room_id: RoomId, terrain: Terrain) {
+        self.terrain.insert(room_id, terrain);
+    }
+
+    pub fn subscribed(&self) -> &[RoomId] {
+        &self.subscribed
+    }
+
+    pub fn set_neighborhood(&mut self, enabled: bool) {
+        self.neighborhood = enabled;
+    }
+
+    /// Rooms shown around `room_id`, or none when only the viewed room is shown.
+    pub fn neighbors_of(&self, room_id: &RoomId) -> Vec<RoomId> {
+        if self.neighborhood {
+            room_id.neighbors()
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn is_shown_neighbor(&self, room_id: &RoomId) -> bool {
+        self.neighborhood && self.current.neighbors().contains(room_id)
+    }
+
+    /// Forgets all room state, keeping subscriptions and terrain.
+    ///
+    /// The first update after (re)subscribing contains the whole room, which only replaces the
+    /// old state cleanly when starting from nothing.
+    pub fn reset(&mut self) {
+        for (room_id, room) in self.rooms.iter_mut() {
+            *room = Room::new(room_id.clone(), self.terrain[room_id].clone());
+        }
+    }
+
+    /// Shows a room in the UI, subscribing to it and its shown neighbors if we aren't already.
+    ///
+    /// The room's terrain must have been added first. Neighbors without terrain are skipped.
+    /// Rooms which are already subscribed to are shown immediately from their cached state.
+    pub fn view(&mut self, room_id: RoomId) -> Result<Subscriptions, Error> {
+        if !self.terrain.contains_key(&room_id) {
+            return Err(format!("no terrain for {}", room_id).into());
+        }
+        let mut changes = Subscriptions::default();
+
+        // the viewed room goes last, so it's the last to be dropped
+        let mut wanted = self.neighbors_of(&room_id);
+        wanted.retain(|id| self.terrain.contains_key(id));
+        wanted.push(room_id.clone());
+        for id in wanted {
+            match self.subscribed.iter().position(|s| *s == id) {
+                Some(idx) => {
+                    let id = self.subscribed.remove(idx);
+                    self.subscribed.push(id);
+                }
+                None => {
+                    let room = Room::new(id.clone(), self.terrain[&id].clone());
+                    self.rooms.insert(id.clone(), room);
+                    self.subscribed.push(id.clone());
+                    changes.subscribe.push(id);
+                }
+            }
+        }
+        while self.subscribed.len() > MAX_SUBSCRIBED_ROOMS {
+            let old = self.subscribed.remove(0);
+            self.rooms.remove(&old);
+            changes.unsubscribe.push(old);
+        }
+
+        self.current = room_id;
+        let room = &self.rooms[&self.current];
+        if room.last_update_time().is_some() {
+            let visual = room.visualize();
+            ui::async_update(&self.ui, |s| s.room(visual))?;
+        }
+        for id in self.neighbors_of(&self.current) {
+            if let Some(room) = self.rooms.get(&id) {
+                if room.last_update_time().is_some() {
+                    let visual = room.visualize();
+                    ui::async_update(&self.ui, |s| s.neighbor_room(visual))?;
+                }
+            }
+        }
+
+        Ok(changes)
+    }
+
+    pub fn handle_message(&mut self, msg: ScreepsMessage<'_>) -> Result<(), Error> {
+        match msg {
+            ScreepsMessage::ChannelUpdate {
+                update:
+                    ChannelUpdate::RoomDetail {
+                        room_name,
+                        shard_name,
+                        update,
+                    },
+            } => {
+                self.apply_update(RoomId::new(shard_name, room_name), update)?;
+            }
+            ScreepsMessage::ChannelUpdate {
+                update:
+                    ChannelUpdate::RoomMapView {
+                        room_name,
+                        shard_name,
+                        update,
+                    },
+            } => {
+                let room_id = RoomId::new(shard_name, room_name);
+                let users = update.users_objects.keys().cloned().collect();
+                ui::async_update(&self.ui, move |s| s.map_room_users(room_id, users))?;
+            }
+            ScreepsMessage::ChannelUpdate {
+                update: ChannelUpdate::UserConsole { user_id, update },
+            } => {
+                if user_id != self.user_id {
+                    warn!(
+                        "received console update for wrong user: expected {}, found {}",
+                        self.user_id, user_id
+                    );
+                }
+                ui::async_update(&self.ui, |s| s.console_update(update))?;
+            }
+            ScreepsMessage::ServerProtocol { protocol } => {
+                debug!("server protocol: {}", protocol);
+            }
+            ScreepsMessage::ServerTime { time } => {
+                debug!("server time: {}", time);
+            }
+            ScreepsMessage::ServerPackage { package } => {
+                debug!("server package: {}", package);
+            }
+            ScreepsMessage::Other(other) => {
+                warn!("Unkown type of screeps message: {}", other);
+            }
+            other => debug!("ignoring {:?}", other),
+        }
+        debug!("handled message successfully");
+
+        Ok(())
+    }
+
+    /// Applies one update to its room, showing the new state if it's shown in the UI.
+    pub fn apply_update(&mut self, update_id: RoomId, update: RoomUpdate) -> Result<(), Error> {
+        let shown_neighbor = self.is_shown_neighbor(&update_id);
+        let room = match self.rooms.get_mut(&update_id) {
+            Some(room) => room,
+            None => {
+                // updates can still arrive shortly after unsubscribing
+                debug!("ignoring update for unsubscribed room {}", update_id);
+                return Ok(());
+            }
+        };
+
+        let events = room
+            .update(update)
+            .with_ctx(|_| format!("handling room update for {}", update_id))?;
+        debug!("updated room {}: {:?}", update_id, room);
+        let time = room.last_update_time();
+        if update_id == self.current {
+            let visual = room.visualize();
+            ui::async_update(&self.ui, |s| s.room(visual))?;
+        } else if shown_neighbor {
+            let visual = room.visualize();
+            ui::async_update(&self.ui, |s| s.neighbor_room(visual))?;
+        }
+        if !events.is_empty() {
+            ui::async_update(&self.ui, move |s| s.room_events(update_id, time, events))?;
+        }
+        Ok(())
+    }
+}
@@ -3,11 +3,12 @@ use std::{
     cmp::{Ordering, PartialOrd},
     collections::{hash_map::Entry, HashMap},
     fmt,
+    str::FromStr,
     sync::Arc,
 };
 
 use err_ctx::ResultExt;
-use log::debug;
+use log::{debug, warn};
 use ndarray::{Array, Ix2};
 use screeps_api::{
     websocket::{flags::Flag, objects::KnownRoomObject, RoomUpdate, RoomUserInfo},
@@ -16,7 +17,15 @@ use screeps_api::{
 
 use crate::net::Error;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+mod events;
+mod map;
+
+pub use self::{
+    events::RoomEvent,
+    map::{owned_rooms_from_json, MapRoom, MapStats, OwnedRoom},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct RoomId {
     pub shard: Option<String>,
     pub room_name: RoomName,
@@ -31,6 +40,59 @@ impl fmt::Display for RoomId {
     }
 }
 
+/// A room, maybe on a specific shard and with a position in it.
+///
+/// Written as `W12N5`, `shard2/W12N5` or `W12N5 25 13`.
+#[derive(Clone, Debug)]
+pub struct Location {
+    pub shard: Option<String>,
+    pub room_name: RoomName,
+    pub pos: Option<(u32, u32)>,
+}
+
+impl Location {
+    /// The room, on `default_shard` unless a shard was given.
+    pub fn room_id(&self, default_shard: Option<&String>) -> RoomId {
+        let shard = self.shard.as_ref().or(default_shard).cloned();
+        RoomId::new(shard, self.room_name)
+    }
+}
+
+impl FromStr for Location {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let room = parts.next().ok_or("expected a room name")?;
+        let (shard, room) = match room.find('/') {
+            Some(idx) => (Some(room[..idx].to_owned()), &room[idx + 1..]),
+            None => (None, room),
+        };
+        if shard.as_ref().map_or(false, |s| s.is_empty()) {
+            return Err(format!("expected a shard before '/' in {:?}", s));
+        }
+        let room_name = RoomName::new(room).map_err(|e| e.to_string())?;
+
+        let coords = parts
+            .map(|part| match part.parse::<u32>() {
+                Ok(c) if c < 50 => Ok(c),
+                _ => Err(format!("invalid coordinate {:?}, expected 0 to 49", part)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let pos = match coords[..] {
+            [] => None,
+            [x, y] => Some((x, y)),
+            _ => return Err(format!("expected both x and y coordinates in {:?}", s)),
+        };
+
+        Ok(Location {
+            shard,
+            room_name,
+            pos,
+        })
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, smart_default::SmartDefault, derive_more::Display)]
 pub enum ConnectionState {
     #[default]
@@ -40,6 +102,14 @@ pub enum ConnectionState {
     Authenticating,
     #[display(fmt = "connected")]
     Connected,
+    #[display(fmt = "reconnecting in {}s", _0)]
+    Reconnecting(u64),
+    #[display(fmt = "playing at {}% speed", _0)]
+    Playing(u32),
+    #[display(fmt = "paused")]
+    Paused,
+    #[display(fmt = "playback finished")]
+    Finished,
     #[display(fmt = "network error occurred, see log")]
     Error,
 }
@@ -48,66 +118,204 @@ impl RoomId {
     pub fn new(shard: Option<String>, room_name: RoomName) -> Self {
         RoomId { shard, room_name }
     }
+
+    /// The room `dx` rooms east and `dy` rooms south of this one, on the same shard.
+    pub fn offset(&self, dx: i32, dy: i32) -> Self {
+        // RoomName treats positive values as "north"
+        RoomId::new(self.shard.clone(), self.room_name + (dx, -dy))
+    }
+
+    /// The eight rooms around this one, row by row from the north-west.
+    pub fn neighbors(&self) -> Vec<RoomId> {
+        let mut neighbors = Vec::with_capacity(8);
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if (dx, dy) != (0, 0) {
+                    neighbors.push(self.offset(dx, dy));
+                }
+            }
+        }
+        neighbors
+    }
+}
+
+/// Terrain of a single room, as rows of tiles.
+#[derive(Clone, Debug)]
+pub struct Terrain {
+    room_name: RoomName,
+    rows: Vec<Vec<TerrainType>>,
+}
+
+impl Terrain {
+    pub fn room_name(&self) -> RoomName {
+        self.room_name
+    }
+
+    /// Reads terrain from a `game/room-terrain` response requested with `encoded=true`, which
+    /// has one digit per tile, row by row.
+    pub fn from_response(response: &serde_json::Value) -> Result<Self, Error> {
+        let terrain = &response["terrain"][0];
+        let room_name = terrain["room"].as_str().ok_or("missing terrain room")?;
+        let room_name = RoomName::new(room_name).map_err(|e| e.into_owned())?;
+        let encoded = terrain["terrain"].as_str().ok_or("missing terrain")?;
+        if encoded.len() != 2500 {
+            return Err(format!("expected 2500 terrain tiles, found {}", encoded.len()).into());
+        }
+        let tiles = encoded
+            .chars()
+            .map(|c| match c {
+                '0' => Ok(TerrainType::Plains),
+                '1' => Ok(TerrainType::Wall),
+                '2' => Ok(TerrainType::Swamp),
+                '3' => Ok(TerrainType::SwampyWall),
+                other => Err(format!("invalid terrain tile {:?}", other)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Terrain {
+            room_name,
+            rows: tiles.chunks(50).map(|row| row.to_vec()).collect(),
+        })
+    }
+}
+
+impl From<&RoomTerrain> for Terrain {
+    fn from(terrain: &RoomTerrain) -> Self {
+        Terrain {
+            room_name: terrain.room_name,
+            rows: terrain
+                .terrain
+                .iter()
+                .map(|row| row.iter().cloned().collect())
+                .collect(),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Room {
     last_update_time: Option<u32>,
     room: RoomId,
-    terrain: RoomTerrain,
+    terrain: Terrain,
     objects: HashMap<String, Arc<KnownRoomObject>>,
+    /// Objects which couldn't be parsed, likely from server mods or new object types.
+    unknown_objects: HashMap<String, Arc<UnknownObject>>,
     flags: Vec<Flag>,
     users: HashMap<String, Arc<RoomUserInfo>>,
 }
 
+/// A room object we don't know how to parse, kept as raw JSON so it can still be shown.
+#[derive(Clone, Debug)]
+pub struct UnknownObject {
+    pub id: String,
+    pub data: serde_json::Value,
+}
+
+impl UnknownObject {
+    /// The object's `type` field, if it has one.
+    pub fn ty(&self) -> Option<&str> {
+        self.data["type"].as_str()
+    }
+
+    /// The object's position, if it has a valid one.
+    fn pos(&self) -> Option<(u32, u32)> {
+        let coord = |name: &str| {
+            self.data[name]
+                .as_u64()
+                .filter(|&c| c < 50)
+                .map(|c| c as u32)
+        };
+        Some((coord("x")?, coord("y")?))
+    }
+}
+
 impl Room {
-    pub fn new(room: RoomId, terrain: RoomTerrain) -> Self {
+    pub fn new(room: RoomId, terrain: Terrain) -> Self {
         assert_eq!(room.room_name, terrain.room_name);
         Room {
             last_update_time: None,
             room,
             terrain,
             objects: HashMap::new(),
+            unknown_objects: HashMap::new(),
             flags: Vec::new(),
             users: HashMap::new(),
         }
     }
 
-    pub fn update(&mut self, update: RoomUpdate) -> Result<(), Error> {
+    /// Game time of the last update, or `None` if the room hasn't been updated yet.
+    pub fn last_update_time(&self) -> Option<u32> {
+        self.last_update_time
+    }
+
+    /// Ids of every object in the room, including ones which couldn't be parsed.
+    pub fn object_ids(&self) -> impl Iterator<Item = &str> {
+        self.objects
+            .keys()
+            .chain(self.unknown_objects.keys())
+            .map(String::as_str)
+    }
+
+    /// Applies an update, returning the events it caused.
+    ///
+    /// The first update after subscribing contains the whole room, so it doesn't produce events.
+    pub fn update(&mut self, update: RoomUpdate) -> Result<Vec<RoomEvent>, Error> {
+        let first_update = self.last_update_time.is_none();
+        let mut events = Vec::new();
+
         debug!("updating metadata");
         if let Some(time) = update.game_time {
             self.last_update_time = Some(time);
         }
         debug!("updating objects");
         for (id, data) in update.objects.into_iter() {
+            let old = self.objects.get(&id).cloned();
             if data.is_null() {
                 self.objects.remove(&id);
+                self.unknown_objects.remove(&id);
+            } else if let Some(unknown) = self.unknown_objects.get_mut(&id) {
+                merge_json(&mut Arc::make_mut(unknown).data, data);
             } else {
                 match self.objects.entry(id.clone()) {
                     Entry::Occupied(entry) => {
-                        Arc::make_mut(entry.into_mut())
-                            .update(data.clone())
-                            .with_ctx(|_| {
-                                format!(
-                                    "updating {} with data {}",
-                                    id,
-                                    serde_json::to_string(&data).unwrap()
-                                )
-                            })?;
-                    }
-                    Entry::Vacant(entry) => {
-                        entry.insert(Arc::new(serde_json::from_value(data.clone()).with_ctx(
-                            |_| {
-                                format!(
-                                    "creating {} with data {}",
-                                    id,
-                                    serde_json::to_string(&data).unwrap()
-                                )
-                            },
-                        )?));
+                        // one bad update shouldn't stop the rest of the room from updating
+                        if let Err(e) = Arc::make_mut(entry.into_mut()).update(data.clone()) {
+                            warn!(
+                                "error updating {} with data {}: {}",
+                                id,
+                                serde_json::to_string(&data).unwrap(),
+                                e
+                            );
+                        }
                     }
+                    Entry::Vacant(entry) => match serde_json::from_value(data.clone()) {
+                        Ok(obj) => {
+                            entry.insert(Arc::new(obj));
+                        }
+                        Err(e) => {
+                            warn!(
+                                "keeping unknown object {} with data {}: {}",
+                                id,
+                                serde_json::to_string(&data).unwrap(),
+                                e
+                            );
+                            self.unknown_objects.insert(
+                                id.clone(),
+                                Arc::new(UnknownObject {
+                                    id: id.clone(),
+                                    data,
+                                }),
+                            );
+                        }
+                    },
                 }
             }
+            if !first_update {
+                events::diff(
+                    old.as_ref().map(|o| &**o),
+                    self.objects.get(&id).map(|o| &**o),
+                    &mut events,
+                );
+            }
         }
         debug!("updating flags");
         self.flags = update.flags;
@@ -146,14 +354,14 @@ impl Room {
 
         debug!("update complete");
 
-        Ok(())
+        Ok(events)
     }
 
     pub fn visualize(&self) -> VisualRoom {
         let mut room =
             VisualRoom::new(self.last_update_time, self.room.clone(), self.users.clone());
 
-        for (row_idx, row) in self.terrain.terrain.iter().enumerate() {
+        for (row_idx, row) in self.terrain.rows.iter().enumerate() {
             for (col_idx, item) in row.iter().enumerate() {
                 if let Some(itt) = InterestingTerrainType::from_terrain(*item) {
                     room.push_top(VisualObject::InterestingTerrain {
@@ -173,11 +381,26 @@ impl Room {
             room.push_top(VisualObject::RoomObject(obj.clone()));
         }
 
+        for obj in self.unknown_objects.values() {
+            if let Some((x, y)) = obj.pos() {
+                room.push_top(VisualObject::Unknown {
+                    x,
+                    y,
+                    obj: obj.clone(),
+                });
+            }
+        }
+
         for list in room.objs.iter_mut() {
             list.sort_unstable();
         }
 
-        room.render_rows();
+        let actions = room
+            .objs
+            .iter()
+            .flat_map(|list| list.iter().flat_map(VisualObject::actions))
+            .collect();
+        room.actions = actions;
 
         room
     }
@@ -213,6 +436,34 @@ pub enum RoomObjectType {
 }
 
 impl RoomObjectType {
+    pub const ALL: &'static [RoomObjectType] = &[
+        RoomObjectType::Road,
+        RoomObjectType::Container,
+        RoomObjectType::Tombstone,
+        RoomObjectType::Resource,
+        RoomObjectType::Rampart,
+        RoomObjectType::ConstructionSite,
+        RoomObjectType::Wall,
+        RoomObjectType::Source,
+        RoomObjectType::Mineral,
+        RoomObjectType::KeeperLair,
+        RoomObjectType::Controller,
+        RoomObjectType::Extractor,
+        RoomObjectType::Extension,
+        RoomObjectType::Spawn,
+        RoomObjectType::Portal,
+        RoomObjectType::Link,
+        RoomObjectType::Storage,
+        RoomObjectType::Tower,
+        RoomObjectType::Observer,
+        RoomObjectType::PowerBank,
+        RoomObjectType::PowerSpawn,
+        RoomObjectType::Lab,
+        RoomObjectType::Terminal,
+        RoomObjectType::Nuker,
+        RoomObjectType::Creep,
+    ];
+
     pub fn of(obj: &KnownRoomObject) -> Self {
         macro_rules! transformit {
             ( $($id:ident),* $(,)? ) => {
@@ -251,6 +502,37 @@ impl RoomObjectType {
             Creep,
         )
     }
+
+    /// The snake case name used in theme files, like `construction_site`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RoomObjectType::Road => "road",
+            RoomObjectType::Container => "container",
+            RoomObjectType::Tombstone => "tombstone",
+            RoomObjectType::Resource => "resource",
+            RoomObjectType::Rampart => "rampart",
+            RoomObjectType::ConstructionSite => "construction_site",
+            RoomObjectType::Wall => "wall",
+            RoomObjectType::Source => "source",
+            RoomObjectType::Mineral => "mineral",
+            RoomObjectType::KeeperLair => "keeper_lair",
+            RoomObjectType::Controller => "controller",
+            RoomObjectType::Extractor => "extractor",
+            RoomObjectType::Extension => "extension",
+            RoomObjectType::Spawn => "spawn",
+            RoomObjectType::Portal => "portal",
+            RoomObjectType::Link => "link",
+            RoomObjectType::Storage => "storage",
+            RoomObjectType::Tower => "tower",
+            RoomObjectType::Observer => "observer",
+            RoomObjectType::PowerBank => "power_bank",
+            RoomObjectType::PowerSpawn => "power_spawn",
+            RoomObjectType::Lab => "lab",
+            RoomObjectType::Terminal => "terminal",
+            RoomObjectType::Nuker => "nuker",
+            RoomObjectType::Creep => "creep",
+        }
+    }
 }
 
 #[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone)]
@@ -260,6 +542,9 @@ pub enum InterestingTerrainType {
 }
 
 impl InterestingTerrainType {
+    pub const ALL: &'static [InterestingTerrainType] =
+        &[InterestingTerrainType::Swamp, InterestingTerrainType::Wall];
+
     pub fn from_terrain(terrain: TerrainType) -> Option<Self> {
         match terrain {
             TerrainType::Plains => None,
@@ -287,6 +572,11 @@ pub enum VisualObject {
     },
     Flag(Flag),
     RoomObject(Arc<KnownRoomObject>),
+    Unknown {
+        x: u32,
+        y: u32,
+        obj: Arc<UnknownObject>,
+    },
 }
 
 impl VisualObject {
@@ -295,6 +585,7 @@ impl VisualObject {
             VisualObject::InterestingTerrain { x, .. } => *x,
             VisualObject::Flag(x) => x.x,
             VisualObject::RoomObject(x) => x.x(),
+            VisualObject::Unknown { x, .. } => *x,
         }
     }
 
@@ -303,56 +594,117 @@ impl VisualObject {
             VisualObject::InterestingTerrain { y, .. } => *y,
             VisualObject::Flag(x) => x.y,
             VisualObject::RoomObject(x) => x.y(),
+            VisualObject::Unknown { y, .. } => *y,
         }
     }
 
-    pub fn to_symbol(&self) -> &'static str {
-        match self {
-            VisualObject::InterestingTerrain {
-                ty: InterestingTerrainType::Swamp,
-                ..
-            } => "⌇",
-            VisualObject::InterestingTerrain {
-                ty: InterestingTerrainType::Wall,
-                ..
-            } => "█",
-            VisualObject::Flag(_) => "F",
-            VisualObject::RoomObject(obj) => match &**obj {
-                KnownRoomObject::ConstructionSite(..) => "△",
-                KnownRoomObject::Container(..) => "▫",
-                KnownRoomObject::Controller(..) => "C",
-                KnownRoomObject::Creep(..) => "●",
-                KnownRoomObject::Extension(..) => "E",
-                KnownRoomObject::Extractor(..) => "X",
-                KnownRoomObject::KeeperLair(..) => "K",
-                KnownRoomObject::Lab(..) => "L",
-                KnownRoomObject::Link(..) => "I",
-                KnownRoomObject::Mineral(..) => "M",
-                KnownRoomObject::Nuker(..) => "N",
-                KnownRoomObject::Observer(..) => "O",
-                KnownRoomObject::Portal(..) => "P",
-                KnownRoomObject::PowerBank(..) => "B",
-                KnownRoomObject::PowerSpawn(..) => "R",
-                KnownRoomObject::Rampart(..) => "▒",
-                KnownRoomObject::Resource(..) => "▪",
-                KnownRoomObject::Road(..) => "╬",
-                KnownRoomObject::Source(..) => "S",
-                KnownRoomObject::Spawn(..) => "P",
-                KnownRoomObject::Storage(..) => "O",
-                KnownRoomObject::Terminal(..) => "T",
-                KnownRoomObject::Tower(..) => "♜",
-                KnownRoomObject::Tombstone(..) => "⚰️",
-                KnownRoomObject::Wall(..) => "W",
-            },
+    /// The id of the user who owns this object, if anyone does.
+    pub fn owner(&self) -> Option<&str> {
+        let obj = match self {
+            VisualObject::RoomObject(obj) => obj,
+            _ => return None,
+        };
+        match &**obj {
+            KnownRoomObject::Creep(x) => Some(&x.user),
+            KnownRoomObject::Lab(x) => Some(&x.user),
+            KnownRoomObject::Link(x) => Some(&x.user),
+            KnownRoomObject::Nuker(x) => Some(&x.user),
+            KnownRoomObject::Observer(x) => Some(&x.user),
+            KnownRoomObject::PowerSpawn(x) => Some(&x.user),
+            KnownRoomObject::Rampart(x) => Some(&x.user),
+            KnownRoomObject::Spawn(x) => Some(&x.user),
+            KnownRoomObject::Storage(x) => Some(&x.user),
+            KnownRoomObject::Terminal(x) => Some(&x.user),
+            KnownRoomObject::Tombstone(x) => Some(&x.user),
+            KnownRoomObject::Tower(x) => Some(&x.user),
+            KnownRoomObject::Controller(x) => x.user.as_ref().map(|s| &**s),
+            KnownRoomObject::Extractor(x) => x.user.as_ref().map(|s| &**s),
+            _ => None,
         }
     }
 
-    pub fn multiple_to_symbol(items: &[VisualObject]) -> &'static str {
-        if let Some(obj) = items.last() {
-            obj.to_symbol()
-        } else {
-            " "
+    /// Hits and maximum hits, for objects which can be damaged.
+    pub fn hits(&self) -> Option<(i32, i32)> {
+        let obj = match self {
+            VisualObject::RoomObject(obj) => obj,
+            _ => return None,
+        };
+        macro_rules! hits {
+            ( $($id:ident),* $(,)? ) => {
+                match &**obj {
+                    $(
+                        KnownRoomObject::$id(x) => Some((x.hits, x.hits_max)),
+                    )*
+                    _ => None,
+                }
+            };
         }
+        hits!(
+            Container,
+            Creep,
+            Extension,
+            Extractor,
+            Lab,
+            Link,
+            Nuker,
+            Observer,
+            PowerBank,
+            PowerSpawn,
+            Rampart,
+            Road,
+            Spawn,
+            Storage,
+            Terminal,
+            Tower,
+            Wall,
+        )
+    }
+
+    /// Actions this object took last tick, from its action log.
+    pub fn actions(&self) -> Vec<VisualAction> {
+        let obj = match self {
+            VisualObject::RoomObject(obj) => obj,
+            _ => return Vec::new(),
+        };
+        let from = (obj.x(), obj.y());
+        let targets = match &**obj {
+            KnownRoomObject::Creep(creep) => {
+                let log = &creep.action_log;
+                vec![
+                    (ActionKind::Attack, &log.attack),
+                    (ActionKind::RangedAttack, &log.ranged_attack),
+                    (ActionKind::Heal, &log.heal),
+                    (ActionKind::RangedHeal, &log.ranged_heal),
+                    (ActionKind::Harvest, &log.harvest),
+                    (ActionKind::Repair, &log.repair),
+                    (ActionKind::Build, &log.build),
+                    (ActionKind::UpgradeController, &log.upgrade_controller),
+                    (ActionKind::ReserveController, &log.reserve_controller),
+                ]
+            }
+            KnownRoomObject::Tower(tower) => {
+                let log = &tower.action_log;
+                vec![
+                    (ActionKind::Attack, &log.attack),
+                    (ActionKind::Heal, &log.heal),
+                    (ActionKind::Repair, &log.repair),
+                ]
+            }
+            KnownRoomObject::Link(link) => {
+                vec![(ActionKind::TransferEnergy, &link.action_log.transfer_energy)]
+            }
+            _ => return Vec::new(),
+        };
+        targets
+            .into_iter()
+            .filter_map(|(kind, target)| {
+                target.as_ref().map(|target| VisualAction {
+                    from,
+                    to: (target.x, target.y),
+                    kind,
+                })
+            })
+            .collect()
     }
 }
 
@@ -376,6 +728,7 @@ impl PartialEq for VisualObject {
             (RoomObject(a), RoomObject(b)) => {
                 RoomObjectType::of(a) == RoomObjectType::of(b) && a.id() == b.id()
             }
+            (Unknown { obj: a, .. }, Unknown { obj: b, .. }) => a.id == b.id,
             (..) => false,
         }
     }
@@ -413,17 +766,61 @@ impl Ord for VisualObject {
             (RoomObject(a), RoomObject(b)) => RoomObjectType::of(a)
                 .cmp(&RoomObjectType::of(b))
                 .then_with(|| a.id().cmp(b.id())),
+            // unknown objects are drawn on top, so that they're noticed
+            (RoomObject(_), _) => Ordering::Less,
+            (_, RoomObject(_)) => Ordering::Greater,
+            (Unknown { obj: a, .. }, Unknown { obj: b, .. }) => a.id.cmp(&b.id),
         }
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ActionKind {
+    Attack,
+    RangedAttack,
+    Heal,
+    RangedHeal,
+    Harvest,
+    Repair,
+    Build,
+    UpgradeController,
+    ReserveController,
+    TransferEnergy,
+}
+
+/// An action from an object's action log, from the actor's position to its target's.
+#[derive(Clone, Debug)]
+pub struct VisualAction {
+    pub from: (u32, u32),
+    pub to: (u32, u32),
+    pub kind: ActionKind,
+}
+
+impl VisualAction {
+    /// Tiles strictly between the actor and the target, each with the direction of travel.
+    pub fn path(&self) -> Vec<((u32, u32), (i32, i32))> {
+        let (fx, fy) = (self.from.0 as i32, self.from.1 as i32);
+        let (dx, dy) = (self.to.0 as i32 - fx, self.to.1 as i32 - fy);
+        let steps = dx.abs().max(dy.abs());
+        let direction = (dx.signum(), dy.signum());
+        (1..steps)
+            .map(|i| {
+                // round to the nearest tile along the line
+                let x = fx + (2 * dx * i + steps).div_euclid(2 * steps);
+                let y = fy + (2 * dy * i + steps).div_euclid(2 * steps);
+                ((x as u32, y as u32), direction)
+            })
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct VisualRoom {
     pub last_update_time: Option<u32>,
     pub room_id: RoomId,
     pub objs: Array<Vec<VisualObject>, Ix2>,
-    pub rendered_rows: Option<Vec<String>>,
     pub users: HashMap<String, Arc<RoomUserInfo>>,
+    pub actions: Vec<VisualAction>,
 }
 
 impl VisualRoom {
@@ -436,8 +833,8 @@ impl VisualRoom {
             last_update_time,
             room_id,
             objs: Array::from_elem((50, 50), Vec::new()),
-            rendered_rows: None,
             users,
+            actions: Vec::new(),
         }
     }
 }
@@ -449,18 +846,27 @@ impl VisualRoom {
             .expect("expected all objects to have valid coordinates (0-49)")
             .push(item);
     }
+}
 
-    fn render_rows(&mut self) {
-        let rows = self
-            .objs
-            .gencolumns()
-            .into_iter()
-            .map(|row| {
-                row.into_iter()
-                    .map(|pos_objs| VisualObject::multiple_to_symbol(&*pos_objs))
-                    .collect::<String>()
-            })
-            .collect::<Vec<_>>();
-        self.rendered_rows = Some(rows);
+/// Applies a partial update to raw JSON the same way the server's room diffs work: objects are
+/// merged key by key, and `null` removes a key.
+fn merge_json(target: &mut serde_json::Value, update: serde_json::Value) {
+    match (target, update) {
+        (serde_json::Value::Object(target), serde_json::Value::Object(update)) => {
+            for (key, value) in update {
+                if value.is_null() {
+                    target.remove(&key);
+                } else {
+                    merge_json(target.entry(key).or_insert(serde_json::Value::Null), value);
+                }
+            }
+        }
+        (target, update) => *target = update,
     }
 }
+
+/// Names an enum variant the way the game does, e.g. `StructureType::PowerSpawn` as
+/// `power-spawn`.
+pub fn kebab_of_debug<T: fmt::Debug>(item: T) -> String {
+    string_morph::to_kebab_case(&format!("{:?}", item))
+}
@@ -0,0 +1,223 @@
+//! Events derived from the differences between one tick's room state and the next.
+use std::fmt;
+
+use screeps_api::websocket::{objects::KnownRoomObject, resources::ResourceType};
+
+use super::{kebab_of_debug, RoomObjectType};
+
+#[derive(Clone, Debug)]
+pub struct RoomEvent {
+    pub x: u32,
+    pub y: u32,
+    pub kind: EventKind,
+}
+
+#[derive(Clone, Debug)]
+pub enum EventKind {
+    Spawned {
+        name: String,
+    },
+    Died {
+        name: String,
+    },
+    Built(RoomObjectType),
+    Destroyed(RoomObjectType),
+    Hits {
+        ty: RoomObjectType,
+        change: i32,
+    },
+    Resources {
+        ty: RoomObjectType,
+        resource: ResourceType,
+        change: i32,
+    },
+    ConstructionProgress {
+        structure: String,
+        progress: i32,
+        total: i32,
+    },
+}
+
+impl fmt::Display for RoomEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>2},{:>2} ", self.x, self.y)?;
+        match &self.kind {
+            EventKind::Spawned { name } => write!(f, "creep {} spawned", name),
+            EventKind::Died { name } => write!(f, "creep {} died", name),
+            EventKind::Built(ty) => write!(f, "{} built", kebab_of_debug(ty)),
+            EventKind::Destroyed(ty) => write!(f, "{} destroyed", kebab_of_debug(ty)),
+            EventKind::Hits { ty, change } => {
+                write!(f, "{} hits {:+}", kebab_of_debug(ty), change)
+            }
+            EventKind::Resources {
+                ty,
+                resource,
+                change,
+            } => write!(
+                f,
+                "{} {} {:+}",
+                kebab_of_debug(ty),
+                kebab_of_debug(resource),
+                change
+            ),
+            EventKind::ConstructionProgress {
+                structure,
+                progress,
+                total,
+            } => write!(f, "constructing {} {}/{}", structure, progress, total),
+        }
+    }
+}
+
+/// Adds events describing the change of one object from `old` to `new`.
+///
+/// `None` means the object didn't exist on that side of the update.
+pub fn diff(
+    old: Option<&KnownRoomObject>,
+    new: Option<&KnownRoomObject>,
+    out: &mut Vec<RoomEvent>,
+) {
+    let obj = match new.or(old) {
+        Some(obj) => obj,
+        None => return,
+    };
+    let mut push = |kind| {
+        out.push(RoomEvent {
+            x: obj.x(),
+            y: obj.y(),
+            kind,
+        })
+    };
+    let ty = RoomObjectType::of(obj);
+
+    let (old, new) = match (old, new) {
+        (None, Some(new)) => {
+            match new {
+                KnownRoomObject::Creep(c) => push(EventKind::Spawned {
+                    name: c.name.clone(),
+                }),
+                _ if is_structure(&ty) => push(EventKind::Built(ty)),
+                _ => (),
+            }
+            return;
+        }
+        (Some(old), None) => {
+            match old {
+                KnownRoomObject::Creep(c) => push(EventKind::Died {
+                    name: c.name.clone(),
+                }),
+                _ if is_structure(&ty) => push(EventKind::Destroyed(ty)),
+                _ => (),
+            }
+            return;
+        }
+        (Some(old), Some(new)) => (old, new),
+        (None, None) => return,
+    };
+
+    if let (Some(old_hits), Some(new_hits)) = (hits(old), hits(new)) {
+        if old_hits != new_hits {
+            push(EventKind::Hits {
+                ty: ty.clone(),
+                change: new_hits - old_hits,
+            });
+        }
+    }
+
+    let old_store = store(old);
+    let new_store = store(new);
+    let resources = old_store
+        .iter()
+        .chain(&new_store)
+        .map(|&(resource, _)| resource);
+    let mut seen = Vec::new();
+    for resource in resources {
+        if seen.contains(&resource) {
+            continue;
+        }
+        seen.push(resource);
+        let change = amount(&new_store, resource) - amount(&old_store, resource);
+        if change != 0 {
+            push(EventKind::Resources {
+                ty: ty.clone(),
+                resource,
+                change,
+            });
+        }
+    }
+
+    if let (KnownRoomObject::ConstructionSite(old), KnownRoomObject::ConstructionSite(new)) =
+        (old, new)
+    {
+        if old.progress != new.progress {
+            push(EventKind::ConstructionProgress {
+                structure: kebab_of_debug(&new.structure_type),
+                progress: new.progress as i32,
+                total: new.progress_total as i32,
+            });
+        }
+    }
+}
+
+fn is_structure(ty: &RoomObjectType) -> bool {
+    match ty {
+        RoomObjectType::Creep
+        | RoomObjectType::Source
+        | RoomObjectType::Mineral
+        | RoomObjectType::Resource
+        | RoomObjectType::Tombstone
+        | RoomObjectType::ConstructionSite => false,
+        _ => true,
+    }
+}
+
+fn hits(obj: &KnownRoomObject) -> Option<i32> {
+    match obj {
+        KnownRoomObject::Spawn(o) => Some(o.hits),
+        KnownRoomObject::Extension(o) => Some(o.hits),
+        KnownRoomObject::Extractor(o) => Some(o.hits),
+        KnownRoomObject::Wall(o) => Some(o.hits),
+        KnownRoomObject::Road(o) => Some(o.hits),
+        KnownRoomObject::Rampart(o) => Some(o.hits),
+        KnownRoomObject::Link(o) => Some(o.hits),
+        KnownRoomObject::Storage(o) => Some(o.hits),
+        KnownRoomObject::Tower(o) => Some(o.hits),
+        KnownRoomObject::Observer(o) => Some(o.hits),
+        KnownRoomObject::PowerBank(o) => Some(o.hits),
+        KnownRoomObject::PowerSpawn(o) => Some(o.hits),
+        KnownRoomObject::Lab(o) => Some(o.hits),
+        KnownRoomObject::Terminal(o) => Some(o.hits),
+        KnownRoomObject::Container(o) => Some(o.hits),
+        KnownRoomObject::Nuker(o) => Some(o.hits),
+        KnownRoomObject::Creep(o) => Some(o.hits),
+        _ => None,
+    }
+}
+
+/// Resources held by an object.
+fn store(obj: &KnownRoomObject) -> Vec<(ResourceType, i32)> {
+    match obj {
+        KnownRoomObject::Storage(o) => o.resources().collect(),
+        KnownRoomObject::Terminal(o) => o.resources().collect(),
+        KnownRoomObject::Container(o) => o.resources().collect(),
+        KnownRoomObject::Tombstone(o) => o.resources().collect(),
+        KnownRoomObject::Creep(o) => o.carry_contents().collect(),
+        KnownRoomObject::Resource(o) => vec![(o.resource_type, o.amount as i32)],
+        KnownRoomObject::Spawn(o) => vec![(ResourceType::Energy, o.energy)],
+        KnownRoomObject::Extension(o) => vec![(ResourceType::Energy, o.energy)],
+        KnownRoomObject::Link(o) => vec![(ResourceType::Energy, o.energy)],
+        KnownRoomObject::Tower(o) => vec![(ResourceType::Energy, o.energy)],
+        KnownRoomObject::PowerSpawn(o) => vec![(ResourceType::Energy, o.energy)],
+        KnownRoomObject::Lab(o) => vec![(ResourceType::Energy, o.energy)],
+        KnownRoomObject::Nuker(o) => vec![(ResourceType::Energy, o.energy)],
+        _ => Vec::new(),
+    }
+}
+
+fn amount(store: &[(ResourceType, i32)], resource: ResourceType) -> i32 {
+    store
+        .iter()
+        .filter(|&&(r, _)| r == resource)
+        .map(|&(_, amount)| amount)
+        .sum()
+}
@@ -0,0 +1,116 @@
+//! Summaries of many rooms at once, for the world map.
+use std::collections::HashMap;
+
+use screeps_api::RoomName;
+
+use super::RoomId;
+
+/// How a room appears on the world map.
+#[derive(Clone, Debug, Default)]
+pub struct MapRoom {
+    /// False for rooms outside the world's borders, or which haven't opened yet.
+    pub open: bool,
+    pub novice: bool,
+    pub respawn: bool,
+    /// User id of the owner, or of the reserver if `level` is 0.
+    pub owner: Option<String>,
+    pub level: u32,
+}
+
+impl MapRoom {
+    pub fn reserved(&self) -> bool {
+        self.owner.is_some() && self.level == 0
+    }
+}
+
+/// Map data for a group of rooms, with the usernames of everyone mentioned.
+#[derive(Clone, Debug, Default)]
+pub struct MapStats {
+    pub rooms: HashMap<RoomName, MapRoom>,
+    pub usernames: HashMap<String, String>,
+}
+
+impl MapStats {
+    /// Parses a response from the `game/map-stats` endpoint.
+    ///
+    /// Rooms with invalid names or missing data are left out.
+    pub fn from_json(data: &serde_json::Value) -> Self {
+        let now = chrono::Utc::now().timestamp_millis();
+        // novice and respawn areas are given as the time they end at
+        let active_until = |value: &serde_json::Value| value.as_i64().map_or(false, |t| t > now);
+
+        let mut stats = MapStats::default();
+        if let Some(rooms) = data["stats"].as_object() {
+            for (name, room) in rooms {
+                let name = match RoomName::new(name) {
+                    Ok(name) => name,
+                    Err(_) => continue,
+                };
+                let map_room = MapRoom {
+                    open: room["status"].as_str() == Some("normal"),
+                    novice: active_until(&room["novice"]),
+                    respawn: active_until(&room["respawnArea"]),
+                    owner: room["own"]["user"].as_str().map(ToOwned::to_owned),
+                    level: room["own"]["level"].as_u64().unwrap_or(0) as u32,
+                };
+                stats.rooms.insert(name, map_room);
+            }
+        }
+        if let Some(users) = data["users"].as_object() {
+            for (id, user) in users {
+                if let Some(username) = user["username"].as_str() {
+                    stats.usernames.insert(id.clone(), username.to_owned());
+                }
+            }
+        }
+        stats
+    }
+}
+
+/// A room the user owns or reserves, for the owned rooms list.
+#[derive(Clone, Debug)]
+pub struct OwnedRoom {
+    pub room_id: RoomId,
+    pub reserved: bool,
+    /// Controller level, if known.
+    pub level: Option<u32>,
+}
+
+/// Parses a response from the `user/rooms` endpoint, owned rooms first.
+///
+/// Servers without shards list rooms directly instead of by shard.
+pub fn owned_rooms_from_json(data: &serde_json::Value) -> Vec<OwnedRoom> {
+    fn add(
+        rooms: &mut Vec<OwnedRoom>,
+        shard: Option<&String>,
+        names: &serde_json::Value,
+        reserved: bool,
+    ) {
+        let names = names.as_array().into_iter().flatten();
+        for name in names.filter_map(|n| n.as_str()) {
+            if let Ok(room_name) = RoomName::new(name) {
+                rooms.push(OwnedRoom {
+                    room_id: RoomId::new(shard.cloned(), room_name),
+                    reserved,
+                    level: None,
+                });
+            }
+        }
+    }
+
+    let mut rooms = Vec::new();
+    for &(key, reserved) in &[("shards", false), ("reservations", true)] {
+        match data[key].as_object() {
+            Some(shards) => {
+                for (shard, names) in shards {
+                    add(&mut rooms, Some(shard), names, reserved);
+                }
+            }
+            None => add(&mut rooms, None, &data[key], reserved),
+        }
+    }
+    if rooms.is_empty() {
+        add(&mut rooms, None, &data["rooms"], false);
+    }
+    rooms
+}
@@ -0,0 +1,98 @@
+//! The last viewed room and UI settings, restored on the next launch.
+//!
+//! Sessions are kept per server, and per token within that, so that several accounts on one
+//! server each get their own. Tokens are only stored as a fingerprint.
+use std::path::PathBuf;
+
+use log::warn;
+use screeps_api::RoomName;
+use serde_json::json;
+
+use crate::{net::Error, room::RoomId, storage};
+
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub room_id: RoomId,
+    pub x: u32,
+    pub y: u32,
+    pub neighborhood: bool,
+    pub show_actions: bool,
+    /// Names of the layers hidden from the room map.
+    pub hidden_layers: Vec<String>,
+}
+
+impl Session {
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "shard": self.room_id.shard,
+            "room": self.room_id.room_name.to_string(),
+            "x": self.x,
+            "y": self.y,
+            "neighborhood": self.neighborhood,
+            "show_actions": self.show_actions,
+            "hidden_layers": self.hidden_layers,
+        })
+    }
+
+    fn from_json(data: &serde_json::Value) -> Option<Self> {
+        let room_name = RoomName::new(data["room"].as_str()?).ok()?;
+        let coord = |name: &str| data[name].as_u64().filter(|&c| c < 50).map(|c| c as u32);
+        Some(Session {
+            room_id: RoomId::new(data["shard"].as_str().map(ToOwned::to_owned), room_name),
+            x: coord("x")?,
+            y: coord("y")?,
+            neighborhood: data["neighborhood"].as_bool().unwrap_or(false),
+            show_actions: data["show_actions"].as_bool().unwrap_or(true),
+            hidden_layers: data["hidden_layers"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|name| name.as_str().map(ToOwned::to_owned))
+                .collect(),
+        })
+    }
+}
+
+/// Which session file and entry to use.
+#[derive(Clone, Debug)]
+pub struct SessionKey {
+    path: PathBuf,
+    token_fingerprint: String,
+}
+
+impl SessionKey {
+    pub fn new(server: &str, token: &[u8]) -> Option<Self> {
+        Some(SessionKey {
+            path: storage::server_file("sessions", server)?,
+            token_fingerprint: fingerprint(token),
+        })
+    }
+
+    pub fn load(&self) -> Option<Session> {
+        match storage::read_json(&self.path) {
+            Ok(data) => Session::from_json(&data?[&self.token_fingerprint]),
+            Err(e) => {
+                warn!("couldn't load last session: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn save(&self, session: &Session) -> Result<(), Error> {
+        let mut data = match storage::read_json(&self.path) {
+            Ok(Some(data @ serde_json::Value::Object(_))) => data,
+            // don't let one broken file stop sessions from being saved forever
+            _ => json!({}),
+        };
+        data[&self.token_fingerprint] = session.to_json();
+        storage::write_json(&self.path, &data)
+    }
+}
+
+/// A 64-bit FNV-1a hash, so the token itself is never written to disk.
+fn fingerprint(token: &[u8]) -> String {
+    let hash = token.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
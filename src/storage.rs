@@ -0,0 +1,79 @@
+//! Files kept between sessions, under the user's config directory.
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use err_ctx::ResultExt;
+
+use crate::net::Error;
+
+/// `$XDG_CONFIG_HOME/srv`, or `~/.config/srv` when that isn't set.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("srv"))
+}
+
+/// A file in `kind`'s directory for things specific to one server, like
+/// `~/.config/srv/bookmarks/screeps.com_api.json`.
+pub fn server_file(kind: &str, server: &str) -> Option<PathBuf> {
+    let name = server
+        .split("://")
+        .last()
+        .unwrap_or(server)
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    let name = name.trim_matches('_');
+    Some(config_dir()?.join(kind).join(format!("{}.json", name)))
+}
+
+/// Reads a JSON file, or returns `None` if it doesn't exist yet.
+pub fn read_json(path: &Path) -> Result<Option<serde_json::Value>, Error> {
+    let data = match fs::read(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        res => res.with_ctx(|_| format!("reading {}", path.display()))?,
+    };
+    let value = serde_json::from_slice(&data).with_ctx(|_| format!("parsing {}", path.display()))?;
+    Ok(Some(value))
+}
+
+/// Writes a JSON file, creating its directory if needed.
+pub fn write_json(path: &Path, value: &serde_json::Value) -> Result<(), Error> {
+    write_file(path, &serde_json::to_vec_pretty(value)?)
+}
+
+/// Writes a file, creating its directory if needed.
+///
+/// The file is replaced all at once, so a crash can't leave it half written. On Unix only the
+/// user can read it, since profiles can hold tokens.
+pub fn write_file(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_ctx(|_| format!("creating {}", dir.display()))?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(&temp)
+        .and_then(|mut file| file.write_all(contents))
+        .with_ctx(|_| format!("writing {}", temp.display()))?;
+    fs::rename(&temp, path).with_ctx(|_| format!("replacing {}", path.display()))?;
+    Ok(())
+}
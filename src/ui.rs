@@ -1,10 +1,15 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::RefCell,
+    cmp,
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+};
 
 use cursive::{
     direction::{Direction, Orientation},
     event::{Event, EventResult, Key, MouseButton, MouseEvent},
     menu::MenuTree,
-    theme::{BaseColor, Color, ColorStyle},
+    theme::{BaseColor, Color, ColorStyle, ColorType},
     utils::markup::StyledString,
     view::*,
     views::*,
@@ -16,12 +21,28 @@ use screeps_api::websocket::UserConsoleUpdate;
 use screeps_api::MyInfo;
 
 use crate::{
+    config::Config,
     net::Command,
-    room::{ConnectionState, RoomId, VisualObject, VisualRoom},
+    room::{
+        ActionKind, ConnectionState, Location, MapStats, OwnedRoom, RoomEvent, RoomId,
+        VisualObject, VisualRoom,
+    },
+    session::{Session, SessionKey},
 };
 
+mod bookmarks;
 mod console;
+mod events;
+mod goto;
 mod info;
+mod layers;
+mod map;
+mod owned;
+mod theme;
+mod tiles;
+mod wizard;
+
+pub use self::{theme::Theme, tiles::TileMode};
 
 mod ids {
     pub const CONN_STATE: &str = "conn-state";
@@ -30,8 +51,11 @@ mod ids {
     pub const ROOM_ID: &str = "room-id";
     pub const LAST_UPDATE_TIME: &str = "last-update-game-time";
     pub const HOVER_INFO: &str = "hover-info";
+    pub const ROOM_VIEW: &str = "room-view";
 
     pub const SHARD_SELECT_LIST: &str = "shard-select-list";
+    pub const BOOKMARK_LIST: &str = "bookmark-list";
+    pub const LAYER_LIST: &str = "layer-list";
 }
 
 #[derive(Clone, Debug, smart_default::SmartDefault)]
@@ -47,6 +71,28 @@ pub struct State {
     #[default(_code = "XY::new(25, 25)")]
     cursor: XY<i32>,
     console: console::ConsoleState,
+    /// Whether to draw last tick's actions over the room.
+    #[default(true)]
+    show_actions: bool,
+    /// Recent snapshots of the current room, oldest first. The last one is live.
+    history: VecDeque<VisualRoom>,
+    #[default(1)]
+    history_capacity: usize,
+    /// Index into `history` being viewed, or `None` when viewing live.
+    scrub: Option<usize>,
+    /// Whether to show the eight rooms around the current one.
+    neighborhood: bool,
+    /// Latest state of the rooms around the current one.
+    neighbors: HashMap<RoomId, VisualRoom>,
+    map: map::MapState,
+    owned: owned::OwnedRooms,
+    bookmarks: bookmarks::Bookmarks,
+    /// Where to save the session on exit, if it's worth restoring.
+    session_key: Option<SessionKey>,
+    theme: Theme,
+    tiles: TileMode,
+    /// Which kinds of objects are drawn on the room map.
+    layers: layers::Layers,
 }
 
 impl State {
@@ -59,6 +105,39 @@ impl State {
             None => warn!("couldn't send command to network thread: no sender attached"),
         }
     }
+
+    /// The objects on a tile in layers that aren't hidden, bottom first.
+    fn visible<'a>(
+        &'a self,
+        room: &'a VisualRoom,
+        x: usize,
+        y: usize,
+    ) -> impl Iterator<Item = &'a VisualObject> + Clone {
+        let layers = &self.layers;
+        room.objs[[x, y]]
+            .iter()
+            .filter(move |obj| layers.shows(obj))
+    }
+
+    /// The glyph and colors for a tile of a room.
+    fn cell<'a>(&'a self, room: &VisualRoom, x: usize, y: usize) -> theme::Cell<'a> {
+        let user_id = self.user_info.as_ref().map(|info| &*info.user_id);
+        self.theme.cell(room, self.visible(room, x, y), user_id)
+    }
+
+    /// The color of a tile in compact mode.
+    fn compact_color(&self, room: &VisualRoom, x: usize, y: usize) -> ColorType {
+        let user_id = self.user_info.as_ref().map(|info| &*info.user_id);
+        self.theme
+            .compact_color(room, self.visible(room, x, y), user_id)
+    }
+
+    /// What to draw next to a tile's glyph in wide mode.
+    fn indicator<'a>(&'a self, room: &VisualRoom, x: usize, y: usize) -> theme::Cell<'a> {
+        let user_id = self.user_info.as_ref().map(|info| &*info.user_id);
+        self.theme
+            .indicator(room, self.visible(room, x, y), user_id)
+    }
 }
 
 pub struct CursiveStatePair<'a, 'b> {
@@ -79,37 +158,165 @@ impl<'a, 'b> CursiveStatePair<'a, 'b> {
         self.state.server = Some(server);
     }
 
+    /// Loads the bookmarks saved for the server being watched.
+    pub fn load_bookmarks(&mut self, server: String) {
+        self.state.bookmarks = bookmarks::Bookmarks::load(&server);
+    }
+
+    pub fn session_key(&mut self, key: SessionKey) {
+        self.state.session_key = Some(key);
+    }
+
+    /// Restores settings from the last session, and the cursor if it's in the same room.
+    pub fn restore_session(&mut self, session: Session, restore_cursor: bool) {
+        self.state.show_actions = session.show_actions;
+        self.state.layers =
+            layers::Layers::from_hidden(session.hidden_layers.iter().map(|name| &**name));
+        if session.neighborhood != self.state.neighborhood {
+            self.toggle_neighborhood();
+        }
+        if restore_cursor {
+            let cursor = XY::new(session.x as i32, session.y as i32);
+            self.siv
+                .find_name::<RoomView>(ids::ROOM_VIEW)
+                .expect("expected to find ROOM_VIEW view")
+                .cursor = cursor;
+            self.cursor(cursor);
+        }
+    }
+
     pub fn user(&mut self, info: MyInfo) {
+        self.username(info.username.clone());
+        self.state.user_info = Some(info);
+    }
+
+    /// Shows a username without full user info, for offline playback.
+    pub fn username(&mut self, username: String) {
         self.siv
             .find_name::<TextView>(ids::USERNAME)
             .expect("expected to find USERNAME view")
-            .set_content(info.username.clone());
-        self.state.user_info = Some(info);
+            .set_content(username);
+    }
+
+    /// Adds keys controlling offline playback: space to pause, n to step, +/- to change speed.
+    pub fn playback_controls(&mut self) {
+        self.siv.add_global_callback(' ', |siv| {
+            sync_update(siv, |s| s.state.send_command(Command::PlaybackTogglePause))
+        });
+        self.siv.add_global_callback('n', |siv| {
+            sync_update(siv, |s| s.state.send_command(Command::PlaybackStep))
+        });
+        self.siv.add_global_callback('+', |siv| {
+            sync_update(siv, |s| s.state.send_command(Command::PlaybackFaster))
+        });
+        self.siv.add_global_callback('-', |siv| {
+            sync_update(siv, |s| s.state.send_command(Command::PlaybackSlower))
+        });
     }
 
     pub fn room(&mut self, room: VisualRoom) {
+        let state = &mut *self.state;
+        if state.history.back().map(|r| &r.room_id) != Some(&room.room_id) {
+            state.history.clear();
+            state.scrub = None;
+        }
+        state.history.push_back(room.clone());
+        let mut viewed_evicted = false;
+        while state.history.len() > state.history_capacity {
+            state.history.pop_front();
+            match &mut state.scrub {
+                Some(0) => viewed_evicted = true,
+                Some(idx) => *idx -= 1,
+                None => (),
+            }
+        }
+
+        match self.state.scrub {
+            None => self.show_room(room),
+            // the snapshot being viewed is gone, so move on to the oldest one left
+            Some(idx) if viewed_evicted => {
+                let oldest = self.state.history[idx].clone();
+                self.show_room(oldest);
+            }
+            Some(_) => self.update_time_display(),
+        }
+    }
+
+    /// Updates one of the rooms shown around the current room.
+    pub fn neighbor_room(&mut self, room: VisualRoom) {
+        self.state.neighbors.insert(room.room_id.clone(), room);
+    }
+
+    fn toggle_neighborhood(&mut self) {
+        self.state.neighborhood = !self.state.neighborhood;
+        let enabled = self.state.neighborhood;
+        self.state.send_command(Command::SetNeighborhood(enabled));
+    }
+
+    fn show_room(&mut self, room: VisualRoom) {
         if self.state.room.as_ref().map(|r| &r.room_id) != Some(&room.room_id) {
+            let neighbors = room.room_id.neighbors();
+            self.state
+                .neighbors
+                .retain(|room_id, _| neighbors.contains(room_id));
+            self.state.owned.select(&mut self.siv, &room.room_id);
             self.siv
                 .find_name::<TextView>(ids::ROOM_ID)
                 .expect("expected to find ROOM_ID view")
                 .set_content(room.room_id.to_string());
         }
-        if let Some(updated) = room.last_update_time {
-            self.siv
-                .find_name::<TextView>(ids::LAST_UPDATE_TIME)
-                .expect("expected to find LAST_UPDATE_TIME view")
-                .set_content(format!("updated: {}", updated));
-        }
         self.state.room = Some(room);
+        self.update_time_display();
         self.update_hover_info();
     }
 
+    fn update_time_display(&mut self) {
+        let viewed = self.state.room.as_ref().and_then(|r| r.last_update_time);
+        let live = self.state.history.back().and_then(|r| r.last_update_time);
+        let text = match (self.state.scrub, viewed, live) {
+            (None, Some(viewed), _) => format!("updated: {}", viewed),
+            (Some(_), Some(viewed), Some(live)) => {
+                format!(
+                    "viewing: {} ({} behind live)",
+                    viewed,
+                    live.saturating_sub(viewed)
+                )
+            }
+            _ => return,
+        };
+        self.siv
+            .find_name::<TextView>(ids::LAST_UPDATE_TIME)
+            .expect("expected to find LAST_UPDATE_TIME view")
+            .set_content(text);
+    }
+
+    /// Moves through the snapshots of recent ticks.
+    fn scrub(&mut self, step: Scrub) {
+        let live = match self.state.history.len() {
+            0 => return,
+            len => len - 1,
+        };
+        let current = self.state.scrub.unwrap_or(live);
+        let new = match step {
+            Scrub::Back => current.saturating_sub(1),
+            Scrub::Forward => cmp::min(current + 1, live),
+            Scrub::Live => live,
+        };
+        self.state.scrub = if new == live { None } else { Some(new) };
+        let room = self.state.history[new].clone();
+        self.show_room(room);
+    }
+
     pub fn conn_state(&mut self, state: ConnectionState) {
         self.state.connection = Some(state);
         let color = match state {
             ConnectionState::Authenticating => BaseColor::Yellow,
             ConnectionState::Connected => BaseColor::Green,
             ConnectionState::Disconnected => BaseColor::Red,
+            ConnectionState::Reconnecting(_) => BaseColor::Yellow,
+            ConnectionState::Playing(_) => BaseColor::Green,
+            ConnectionState::Paused => BaseColor::Yellow,
+            ConnectionState::Finished => BaseColor::Blue,
             ConnectionState::Error => BaseColor::Red,
         };
 
@@ -126,6 +333,108 @@ impl<'a, 'b> CursiveStatePair<'a, 'b> {
         }
     }
 
+    fn bookmark_popup(&mut self) {
+        if self
+            .siv
+            .find_name::<MenuPopup>(ids::BOOKMARK_LIST)
+            .is_some()
+        {
+            return;
+        }
+        let list = self.state.bookmarks.list();
+        let mut menu = MenuTree::new();
+        for bookmark in list {
+            let label = format!(
+                "{} ({} {},{})",
+                bookmark.name, bookmark.room_id, bookmark.x, bookmark.y
+            );
+            let cloned_bookmark = bookmark.clone();
+            menu.add_leaf(label, move |s| {
+                let b = cloned_bookmark.clone();
+                sync_update(s, move |s| s.jump_to(b.room_id, b.x, b.y));
+            });
+        }
+        if !list.is_empty() {
+            menu.add_delimiter();
+        }
+        menu.add_leaf("bookmark current position...", |s| {
+            s.add_layer(bookmarks::name_prompt());
+        });
+        if !list.is_empty() {
+            let mut delete = MenuTree::new();
+            for bookmark in list {
+                let name = bookmark.name.clone();
+                delete.add_leaf(&*bookmark.name, move |s| {
+                    let name = name.clone();
+                    sync_update(s, move |s| s.remove_bookmark(&name));
+                });
+            }
+            menu.add_subtree("delete", delete);
+        }
+        let popup = MenuPopup::new(Rc::new(menu));
+        let layer = LinearLayout::new(Orientation::Vertical)
+            .child(TextView::new("Bookmarks"))
+            .child(popup.with_name(ids::BOOKMARK_LIST));
+        self.siv.add_layer(layer);
+        self.siv
+            .focus(&Selector::Name(ids::BOOKMARK_LIST))
+            .expect("just added bookmark list");
+    }
+
+    fn add_bookmark(&mut self, name: String) {
+        let room_id = match &self.state.room {
+            Some(room) => room.room_id.clone(),
+            None => return,
+        };
+        let bookmark = bookmarks::Bookmark {
+            name,
+            room_id,
+            x: self.state.cursor.x as u32,
+            y: self.state.cursor.y as u32,
+        };
+        if let Err(e) = self.state.bookmarks.add(bookmark) {
+            warn!("couldn't save bookmark: {}", e);
+            self.console_error(format!("couldn't save bookmark: {}", e));
+        }
+    }
+
+    fn remove_bookmark(&mut self, name: &str) {
+        if let Err(e) = self.state.bookmarks.remove(name) {
+            warn!("couldn't save bookmarks: {}", e);
+            self.console_error(format!("couldn't save bookmarks: {}", e));
+        }
+    }
+
+    fn toggle_layer(&mut self, layer: layers::Layer) {
+        self.state.layers.toggle(layer);
+    }
+
+    fn layer_popup(&mut self) {
+        if self.siv.find_name::<MenuPopup>(ids::LAYER_LIST).is_some() {
+            return;
+        }
+        let mut menu = MenuTree::new();
+        for &layer in layers::Layer::ALL {
+            let mark = if self.state.layers.is_shown(layer) {
+                "[x]"
+            } else {
+                "[ ]"
+            };
+            let label = format!("{} {} ({})", mark, layer.name(), layer.key());
+            menu.add_leaf(label, move |s| {
+                sync_update(s, |s| s.toggle_layer(layer));
+            });
+        }
+        let popup = MenuPopup::new(Rc::new(menu));
+        let layer = LinearLayout::new(Orientation::Vertical)
+            .child(TextView::new("Show layers"))
+            .child(popup.with_name(ids::LAYER_LIST));
+        self.siv.add_layer(layer);
+        self.siv
+            .focus(&Selector::Name(ids::LAYER_LIST))
+            .expect("just added layer list");
+    }
+
     fn shard_select_popup(&mut self) {
         if self
             .siv
@@ -162,6 +471,69 @@ impl<'a, 'b> CursiveStatePair<'a, 'b> {
             .expect("just added shard list");
     }
 
+    fn open_map(&mut self) {
+        if self.siv.find_name::<map::WorldMap>(map::WORLD_MAP).is_some() {
+            return;
+        }
+        let center = match &self.state.room {
+            Some(room) => room.room_id.clone(),
+            None => return,
+        };
+        let rooms = self.state.map.open(center);
+        self.state.send_command(Command::OpenMap(rooms));
+        self.siv.add_layer(map::view());
+    }
+
+    fn close_map(&mut self) {
+        if self.siv.find_name::<map::WorldMap>(map::WORLD_MAP).is_some() {
+            self.siv.pop_layer();
+            self.state.send_command(Command::CloseMap);
+        }
+    }
+
+    fn open_room_from_map(&mut self, room_id: RoomId) {
+        self.close_map();
+        if self.state.room.as_ref().map(|r| &r.room_id) != Some(&room_id) {
+            self.state.send_command(Command::ChangeRoom(room_id));
+        }
+    }
+
+    pub fn map_stats(&mut self, stats: MapStats) {
+        self.state.map.add_stats(stats);
+    }
+
+    /// Sets which users have objects in a room shown on the map or in the owned rooms list.
+    pub fn map_room_users(&mut self, room_id: RoomId, users: Vec<String>) {
+        let my_id = self.state.user_info.as_ref().map(|info| &info.user_id);
+        let hostiles = users.iter().any(|user| Some(user) != my_id);
+        let current = self.state.room.as_ref().map(|r| r.room_id.clone());
+        self.state
+            .owned
+            .set_hostiles(&mut self.siv, room_id.clone(), hostiles, current.as_ref());
+        self.state.map.set_present_users(room_id.room_name, users);
+    }
+
+    pub fn owned_rooms(&mut self, rooms: Vec<OwnedRoom>) {
+        let current = self.state.room.as_ref().map(|r| r.room_id.clone());
+        self.state
+            .owned
+            .set_rooms(&mut self.siv, rooms, current.as_ref());
+    }
+
+    fn open_owned_room(&mut self, room_id: RoomId) {
+        if self.state.room.as_ref().map(|r| &r.room_id) != Some(&room_id) {
+            self.state.send_command(Command::ChangeRoom(room_id));
+        }
+    }
+
+    /// Switches to the next or previous room in the owned rooms list.
+    fn cycle_owned_rooms(&mut self, step: isize) {
+        let current = self.state.room.as_ref().map(|r| &r.room_id);
+        if let Some(room_id) = self.state.owned.step_from(current, step) {
+            self.open_owned_room(room_id);
+        }
+    }
+
     pub fn command_sender(&mut self, send: UnboundedSender<Command>) {
         self.state.send = Some(send);
     }
@@ -172,6 +544,33 @@ impl<'a, 'b> CursiveStatePair<'a, 'b> {
         self.update_hover_info();
     }
 
+    /// Moves the cursor to a position, changing rooms if needed.
+    fn jump_to(&mut self, room_id: RoomId, x: u32, y: u32) {
+        if self.state.room.as_ref().map(|r| &r.room_id) != Some(&room_id) {
+            self.state.send_command(Command::ChangeRoom(room_id));
+        }
+        let cursor = XY::new(x as i32, y as i32);
+        self.siv
+            .find_name::<RoomView>(ids::ROOM_VIEW)
+            .expect("expected to find ROOM_VIEW view")
+            .cursor = cursor;
+        self.siv
+            .focus(&Selector::Name(ids::ROOM_VIEW))
+            .expect("expected to find ROOM_VIEW view");
+        self.cursor(cursor);
+    }
+
+    /// Moves to a location typed into the go-to prompt, staying on the current shard by default.
+    fn go_to(&mut self, location: Location) {
+        let current = self.state.room.as_ref().map(|r| r.room_id.clone());
+        let room_id = location.room_id(current.as_ref().and_then(|id| id.shard.as_ref()));
+        let cursor = self.state.cursor;
+        let (x, y) = location
+            .pos
+            .unwrap_or((cursor.x as u32, cursor.y as u32));
+        self.jump_to(room_id, x, y);
+    }
+
     fn update_hover_info(&mut self) {
         if let Some(room) = &self.state.room {
             let things = room
@@ -193,6 +592,24 @@ impl<'a, 'b> CursiveStatePair<'a, 'b> {
     pub fn console_update(&mut self, update: UserConsoleUpdate) {
         self.state.console.console_update(&mut self.siv, update);
     }
+
+    pub fn room_events(&mut self, room_id: RoomId, time: Option<u32>, events: Vec<RoomEvent>) {
+        events::add_events(&mut self.siv, room_id, time, events);
+    }
+
+    pub fn console_error(&mut self, message: String) {
+        self.state.console.error(&mut self.siv, message);
+    }
+
+    fn console_submit(&mut self, expression: &str) {
+        if let Some(expression) = self.state.console.submit(&mut self.siv, expression) {
+            self.state.send_command(Command::ExecuteConsole(expression));
+        }
+    }
+
+    fn console_history(&mut self, direction: console::HistoryDirection) {
+        self.state.console.history(&mut self.siv, direction);
+    }
 }
 
 thread_local! {
@@ -212,15 +629,60 @@ pub fn async_update<F: FnOnce(&mut CursiveStatePair) + Send + 'static>(
     .map_err(|e| format!("{}", e).into())
 }
 
+/// Saves the viewed room and settings, to be restored on the next launch.
+pub fn save_session() {
+    STATE.with(|state| {
+        let state = state.borrow();
+        let (key, room) = match (&state.session_key, &state.room) {
+            (Some(key), Some(room)) => (key, room),
+            _ => return,
+        };
+        let session = Session {
+            room_id: room.room_id.clone(),
+            x: state.cursor.x as u32,
+            y: state.cursor.y as u32,
+            neighborhood: state.neighborhood,
+            show_actions: state.show_actions,
+            hidden_layers: state
+                .layers
+                .hidden_names()
+                .into_iter()
+                .map(ToOwned::to_owned)
+                .collect(),
+        };
+        if let Err(e) = key.save(&session) {
+            warn!("couldn't save session: {}", e);
+        }
+    })
+}
+
 fn sync_update<F: FnOnce(&mut CursiveStatePair)>(siv: &mut Cursive, func: F) {
     STATE.with(|state| {
         func(&mut CursiveStatePair::new(siv, &mut state.borrow_mut()));
     })
 }
 
-pub fn setup(c: &mut Cursive) {
+/// Shows the setup wizard, which saves a profile and then sets up the rest of the UI.
+pub fn setup_wizard(c: &mut Cursive, config: Config) {
+    c.add_layer(wizard::view(config));
+}
+
+pub fn setup(c: &mut Cursive, config: &Config) {
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        s.history_capacity = cmp::max(config.history_ticks, 1);
+        s.theme = config.theme.clone();
+        s.tiles = config.tiles.unwrap_or_default();
+    });
+
+    let mut room_view = RoomView::new();
+    if let Some((x, y)) = config.room.as_ref().and_then(|location| location.pos) {
+        room_view.cursor = XY::new(x as i32, y as i32);
+        STATE.with(|s| s.borrow_mut().cursor = room_view.cursor);
+    }
+
     let mut layout = LinearLayout::new(Orientation::Horizontal);
-    layout.add_child(RoomView::new());
+    layout.add_child(room_view.with_name(ids::ROOM_VIEW));
 
     let mut sidebar = LinearLayout::new(Orientation::Vertical);
     sidebar.add_child(TextView::new("").with_name(ids::SERVER_STATE));
@@ -228,6 +690,7 @@ pub fn setup(c: &mut Cursive) {
     sidebar.add_child(TextView::new("").with_name(ids::USERNAME));
     sidebar.add_child(TextView::new("").with_name(ids::ROOM_ID));
     sidebar.add_child(TextView::new("").with_name(ids::LAST_UPDATE_TIME));
+    sidebar.add_child(STATE.with(|s| s.borrow().owned.view()));
     sidebar.add_child(
         TextView::new("")
             .with_name(ids::HOVER_INFO)
@@ -236,7 +699,10 @@ pub fn setup(c: &mut Cursive) {
 
     layout.add_child(sidebar);
 
-    layout.add_child(STATE.with(|s| s.borrow().console.view()));
+    let mut right_column = LinearLayout::new(Orientation::Vertical);
+    right_column.add_child(events::view());
+    right_column.add_child(STATE.with(|s| s.borrow().console.view()));
+    layout.add_child(right_column);
 
     // layout.add_child(
     //     DebugView::new()
@@ -247,6 +713,31 @@ pub fn setup(c: &mut Cursive) {
     c.add_layer(layout);
     c.add_global_callback('q', |c| c.quit());
     c.add_global_callback('s', |siv| sync_update(siv, |s| s.shard_select_popup()));
+    c.add_global_callback('a', |siv| {
+        sync_update(siv, |s| s.state.show_actions = !s.state.show_actions)
+    });
+    c.add_global_callback(',', |siv| sync_update(siv, |s| s.scrub(Scrub::Back)));
+    c.add_global_callback('.', |siv| sync_update(siv, |s| s.scrub(Scrub::Forward)));
+    c.add_global_callback('/', |siv| sync_update(siv, |s| s.scrub(Scrub::Live)));
+    c.add_global_callback('3', |siv| sync_update(siv, |s| s.toggle_neighborhood()));
+    c.add_global_callback('m', |siv| sync_update(siv, |s| s.open_map()));
+    c.add_global_callback('g', |siv| siv.add_layer(goto::view()));
+    c.add_global_callback('b', |siv| sync_update(siv, |s| s.bookmark_popup()));
+    c.add_global_callback('[', |siv| sync_update(siv, |s| s.cycle_owned_rooms(-1)));
+    c.add_global_callback(']', |siv| sync_update(siv, |s| s.cycle_owned_rooms(1)));
+    c.add_global_callback('v', |siv| sync_update(siv, |s| s.layer_popup()));
+    for &layer in layers::Layer::ALL {
+        c.add_global_callback(layer.key(), move |siv| {
+            sync_update(siv, |s| s.toggle_layer(layer))
+        });
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Scrub {
+    Back,
+    Forward,
+    Live,
 }
 
 #[derive(Clone, Debug, smart_default::SmartDefault)]
@@ -266,37 +757,39 @@ impl View for RoomView {
         STATE.with(|state| {
             let state = state.borrow();
             if let Some(room) = state.room.as_ref() {
-                let rendered = room
-                    .rendered_rows
-                    .as_ref()
-                    .expect("expected rows to be rendered");
-                for (idx, row_text) in rendered.iter().enumerate() {
-                    let pos = (1, idx + 1);
-                    printer.print(pos, row_text);
+                let tiles = state.tiles;
+                let origin = room_origin(state.neighborhood, tiles);
+                if state.neighborhood {
+                    draw_neighborhood_borders(printer, state.theme.ascii(), tiles.room_cell());
+                    for dy in -1..=1 {
+                        for dx in -1..=1 {
+                            let neighbor = match state.neighbors.get(&room.room_id.offset(dx, dy)) {
+                                Some(neighbor) if (dx, dy) != (0, 0) => neighbor,
+                                _ => continue,
+                            };
+                            let cell = tiles.room_cell();
+                            let cell_origin =
+                                Vec2::new((dx + 1) as usize * cell.x, (dy + 1) as usize * cell.y);
+                            draw_room(&printer.offset(cell_origin), neighbor, &state);
+                        }
+                    }
+                }
+                let printer = printer.offset(origin);
+                draw_room(&printer, room, &state);
+                // on_event keeps the cursor in the room, this is just in case
+                let x = self.cursor.x.max(0).min(49) as usize;
+                let y = self.cursor.y.max(0).min(49) as usize;
+                if tiles == TileMode::Compact {
+                    draw_tile_pair(&printer, room, &state, x, y - y % 2, Some(y));
+                    return;
+                }
+                let mut symbol_at_cursor = state.cell(room, x, y).glyph.to_owned();
+                if tiles == TileMode::Wide {
+                    symbol_at_cursor.push_str(state.indicator(room, x, y).glyph);
                 }
-                let cursor_ui_pos = ((self.cursor.x + 1) as usize, (self.cursor.y + 1) as usize);
-                let symbol_at_cursor = if self.cursor.x >= 0
-                    && self.cursor.x < 50
-                    && self.cursor.y >= 0
-                    && self.cursor.y < 50
-                {
-                    VisualObject::multiple_to_symbol(
-                        room.objs
-                            .get((self.cursor.x as usize, self.cursor.y as usize))
-                            .unwrap(),
-                    )
-                } else {
-                    " "
-                };
                 printer.print_styled(
-                    cursor_ui_pos,
-                    From::from(&StyledString::styled(
-                        symbol_at_cursor,
-                        ColorStyle {
-                            front: Color::Dark(BaseColor::Magenta).into(),
-                            back: Color::Light(BaseColor::Cyan).into(),
-                        },
-                    )),
+                    tiles.tile_pos(x, y),
+                    From::from(&StyledString::styled(symbol_at_cursor, cursor_style())),
                 );
             }
         });
@@ -318,10 +811,21 @@ impl View for RoomView {
                 position,
                 event: MouseEvent::Press(MouseButton::Left),
                 ..
-            } => Move::Abs(
-                position.x as i32 - offset.x as i32 - 1,
-                position.y as i32 - offset.y as i32 - 1,
-            ),
+            } => {
+                let (origin, tiles) = STATE.with(|state| {
+                    let state = state.borrow();
+                    (room_origin(state.neighborhood, state.tiles), state.tiles)
+                });
+                let mut tile = tiles.tile_at(XY::new(
+                    position.x as i32 - (offset.x + origin.x) as i32,
+                    position.y as i32 - (offset.y + origin.y) as i32,
+                ));
+                // a compact cell holds two tiles, so clicking it again picks the lower one
+                if tiles == TileMode::Compact && tile == self.cursor {
+                    tile.y += 1;
+                }
+                Move::Abs(tile.x, tile.y)
+            }
             _ => return EventResult::Ignored,
         };
 
@@ -338,8 +842,7 @@ impl View for RoomView {
         }
 
         let rdx = self.cursor.x.div_euclid(50);
-        // we treat negative values as "north", RoomName treats negative values as "south"
-        let rdy = -self.cursor.y.div_euclid(50);
+        let rdy = self.cursor.y.div_euclid(50);
         self.cursor.x = self.cursor.x.rem_euclid(50);
         self.cursor.y = self.cursor.y.rem_euclid(50);
 
@@ -348,8 +851,7 @@ impl View for RoomView {
 
             if rdx != 0 || rdy != 0 {
                 if let Some(visual_room) = &state.room {
-                    let new_room_name = visual_room.room_id.room_name + (rdx, rdy);
-                    let new_room = RoomId::new(visual_room.room_id.shard.clone(), new_room_name);
+                    let new_room = visual_room.room_id.offset(rdx, rdy);
                     debug!("changing room from {} to {}", visual_room.room_id, new_room);
                     state.send_command(Command::ChangeRoom(new_room));
                 }
@@ -365,6 +867,192 @@ impl View for RoomView {
     }
 
     fn required_size(&mut self, _: Vec2) -> Vec2 {
-        Vec2::new(52, 52)
+        STATE.with(|state| {
+            let state = state.borrow();
+            let cell = state.tiles.room_cell();
+            if state.neighborhood {
+                Vec2::new(cell.x * 3 + 1, cell.y * 3 + 1)
+            } else {
+                cell + (1, 1)
+            }
+        })
+    }
+}
+
+/// Where the current room is drawn in the room view, relative to its border.
+fn room_origin(neighborhood: bool, tiles: TileMode) -> Vec2 {
+    if neighborhood {
+        tiles.room_cell()
+    } else {
+        Vec2::zero()
+    }
+}
+
+/// Draws a room's tiles one cell in from the printer's origin, leaving space for a border.
+fn draw_room(printer: &Printer, room: &VisualRoom, state: &State) {
+    for y in 0..50 {
+        for x in 0..50 {
+            match state.tiles {
+                TileMode::Compact if y % 2 == 0 => draw_tile_pair(printer, room, state, x, y, None),
+                TileMode::Compact => (),
+                TileMode::Normal | TileMode::Wide => draw_tile(printer, room, state, x, y),
+            }
+        }
+    }
+    // arrows would cover the half blocks, which each show two tiles
+    if state.show_actions && state.tiles != TileMode::Compact {
+        draw_actions(printer, room, state);
+    }
+}
+
+/// Draws one tile, and its indicator in wide mode.
+fn draw_tile(printer: &Printer, room: &VisualRoom, state: &State, x: usize, y: usize) {
+    let pos = state.tiles.tile_pos(x, y);
+    let cell = state.cell(room, x, y);
+    printer.with_color(cell.style, |printer| printer.print(pos, cell.glyph));
+    if state.tiles == TileMode::Wide {
+        let indicator = state.indicator(room, x, y);
+        printer.with_color(indicator.style, |printer| {
+            printer.print(pos + (1, 0), indicator.glyph)
+        });
+    }
+}
+
+/// Draws two tiles, `y` and the one below it, in one cell for compact mode, highlighting the
+/// cursor if it's on either.
+fn draw_tile_pair(
+    printer: &Printer,
+    room: &VisualRoom,
+    state: &State,
+    x: usize,
+    y: usize,
+    cursor_y: Option<usize>,
+) {
+    let pos = state.tiles.tile_pos(x, y);
+    if state.theme.ascii() {
+        // without half blocks, show the tile with the cursor or the one whose top
+        // object sorts higher, which puts creeps and structures over terrain
+        let shown = cursor_y.unwrap_or_else(|| {
+            if state.visible(room, x, y + 1).last() > state.visible(room, x, y).last() {
+                y + 1
+            } else {
+                y
+            }
+        });
+        let cell = state.cell(room, x, shown);
+        let style = if cursor_y.is_some() {
+            cursor_style()
+        } else {
+            cell.style
+        };
+        printer.with_color(style, |printer| printer.print(pos, cell.glyph));
+        return;
+    }
+    let color = |tile_y| {
+        if cursor_y == Some(tile_y) {
+            cursor_style().back
+        } else {
+            state.compact_color(room, x, tile_y)
+        }
+    };
+    let style = ColorStyle {
+        front: color(y),
+        back: color(y + 1),
+    };
+    printer.with_color(style, |printer| printer.print(pos, "▀"));
+}
+
+fn cursor_style() -> ColorStyle {
+    ColorStyle {
+        front: Color::Dark(BaseColor::Magenta).into(),
+        back: Color::Light(BaseColor::Cyan).into(),
+    }
+}
+
+/// Draws the lines separating the nine rooms of the neighborhood view.
+fn draw_neighborhood_borders(printer: &Printer, ascii: bool, cell: Vec2) {
+    let size = Vec2::new(cell.x * 3 + 1, cell.y * 3 + 1);
+    let (horizontal, vertical, cross) = if ascii {
+        ("-", "|", "+")
+    } else {
+        ("─", "│", "┼")
+    };
+    printer.with_color(ColorStyle::secondary(), |printer| {
+        for i in 0..4 {
+            printer.print_hline((0, i * cell.y), size.x, horizontal);
+            printer.print_vline((i * cell.x, 0), size.y, vertical);
+        }
+        for y in 0..4 {
+            for x in 0..4 {
+                printer.print((x * cell.x, y * cell.y), cross);
+            }
+        }
+    });
+}
+
+/// Draws each action as arrows from the actor towards the target, and highlights the target.
+fn draw_actions(printer: &Printer, room: &VisualRoom, state: &State) {
+    for action in &room.actions {
+        let color = Color::Dark(action_color(action.kind));
+        for ((x, y), direction) in action.path() {
+            printer.print_styled(
+                state.tiles.tile_pos(x as usize, y as usize),
+                From::from(&StyledString::styled(
+                    direction_arrow(direction, state.theme.ascii()),
+                    color,
+                )),
+            );
+        }
+        let (x, y) = action.to;
+        if x >= 50 || y >= 50 {
+            continue;
+        }
+        let target_symbol = state.cell(room, x as usize, y as usize).glyph;
+        printer.print_styled(
+            state.tiles.tile_pos(x as usize, y as usize),
+            From::from(&StyledString::styled(
+                target_symbol,
+                ColorStyle {
+                    front: Color::Light(BaseColor::White).into(),
+                    back: color.into(),
+                },
+            )),
+        );
+    }
+}
+
+fn action_color(kind: ActionKind) -> BaseColor {
+    match kind {
+        ActionKind::Attack | ActionKind::RangedAttack => BaseColor::Red,
+        ActionKind::Heal | ActionKind::RangedHeal => BaseColor::Green,
+        ActionKind::Harvest => BaseColor::Yellow,
+        ActionKind::Repair | ActionKind::Build => BaseColor::Blue,
+        ActionKind::UpgradeController | ActionKind::ReserveController => BaseColor::Magenta,
+        ActionKind::TransferEnergy => BaseColor::Cyan,
+    }
+}
+
+fn direction_arrow((dx, dy): (i32, i32), ascii: bool) -> &'static str {
+    if ascii {
+        return match (dx, dy) {
+            (1, 0) => ">",
+            (-1, 0) => "<",
+            (0, -1) => "^",
+            (0, 1) => "v",
+            (1, -1) | (-1, 1) => "/",
+            (-1, -1) | (1, 1) => "\\",
+            _ => ".",
+        };
+    }
+    match (dx, dy) {
+        (1, 0) => "→",
+        (-1, 0) => "←",
+        (0, -1) => "↑",
+        (0, 1) => "↓",
+        (1, -1) => "↗",
+        (-1, -1) => "↖",
+        (1, 1) => "↘",
+        (-1, 1) => "↙",
+        _ => "·",
     }
 }
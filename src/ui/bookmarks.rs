@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+
+use cursive::{
+    direction::Orientation,
+    event::Key,
+    theme::{BaseColor, Color},
+    utils::markup::StyledString,
+    view::*,
+    views::*,
+    Cursive,
+};
+use log::warn;
+use screeps_api::RoomName;
+use serde_json::json;
+
+use crate::{net::Error, room::RoomId, storage};
+
+pub const BOOKMARK_NAME_INPUT: &str = "bookmark-name-input";
+pub const BOOKMARK_ERROR: &str = "bookmark-error";
+
+#[derive(Clone, Debug)]
+pub struct Bookmark {
+    pub name: String,
+    pub room_id: RoomId,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Bookmark {
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "name": self.name,
+            "shard": self.room_id.shard,
+            "room": self.room_id.room_name.to_string(),
+            "x": self.x,
+            "y": self.y,
+        })
+    }
+
+    fn from_json(data: &serde_json::Value) -> Option<Self> {
+        let room_name = RoomName::new(data["room"].as_str()?).ok()?;
+        let coord = |name: &str| data[name].as_u64().filter(|&c| c < 50).map(|c| c as u32);
+        Some(Bookmark {
+            name: data["name"].as_str()?.to_owned(),
+            room_id: RoomId::new(data["shard"].as_str().map(ToOwned::to_owned), room_name),
+            x: coord("x")?,
+            y: coord("y")?,
+        })
+    }
+}
+
+/// Bookmarked rooms for the current server, saved whenever they change.
+#[derive(Clone, Debug, Default)]
+pub struct Bookmarks {
+    /// `None` until a server is known, or if there's nowhere to save to.
+    path: Option<PathBuf>,
+    list: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    /// Loads the bookmarks saved for a server.
+    pub fn load(server: &str) -> Self {
+        let path = storage::server_file("bookmarks", server);
+        let data = match path.as_ref().map(|path| storage::read_json(path)) {
+            Some(Ok(data)) => data,
+            Some(Err(e)) => {
+                warn!("couldn't load bookmarks: {}", e);
+                None
+            }
+            None => {
+                warn!("no config directory to store bookmarks in");
+                None
+            }
+        };
+        let list = data
+            .as_ref()
+            .and_then(|data| data.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(Bookmark::from_json)
+            .collect();
+        Bookmarks { path, list }
+    }
+
+    pub fn list(&self) -> &[Bookmark] {
+        &self.list
+    }
+
+    /// Adds a bookmark, replacing any with the same name.
+    pub fn add(&mut self, bookmark: Bookmark) -> Result<(), Error> {
+        match self.list.iter_mut().find(|b| b.name == bookmark.name) {
+            Some(existing) => *existing = bookmark,
+            None => self.list.push(bookmark),
+        }
+        self.save()
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), Error> {
+        self.list.retain(|b| b.name != name);
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        let path = self
+            .path
+            .as_ref()
+            .ok_or("bookmarks can't be saved without a server and config directory")?;
+        let data = self.list.iter().map(Bookmark::to_json).collect();
+        storage::write_json(path, &serde_json::Value::Array(data))
+    }
+}
+
+/// A prompt for the name to bookmark the current room and cursor under.
+pub fn name_prompt() -> impl View + 'static {
+    let input = EditView::new()
+        .on_submit(submit_name)
+        .with_name(BOOKMARK_NAME_INPUT)
+        .fixed_width(30);
+    let dialog = Dialog::around(
+        LinearLayout::new(Orientation::Vertical)
+            .child(input)
+            .child(TextView::new("").with_name(BOOKMARK_ERROR)),
+    )
+    .title("bookmark name")
+    .dismiss_button("Cancel");
+    OnEventView::new(dialog).on_event(Key::Esc, |siv| {
+        siv.pop_layer();
+    })
+}
+
+fn submit_name(siv: &mut Cursive, name: &str) {
+    let name = name.trim();
+    if name.is_empty() {
+        siv.find_name::<TextView>(BOOKMARK_ERROR)
+            .expect("expected to find BOOKMARK_ERROR view")
+            .set_content(StyledString::styled(
+                "a name is required",
+                Color::Dark(BaseColor::Red),
+            ));
+        return;
+    }
+    let name = name.to_owned();
+    siv.pop_layer();
+    super::sync_update(siv, |s| s.add_bookmark(name));
+}
@@ -1,6 +1,8 @@
 use std::mem;
 
 use cursive::{
+    direction::Orientation,
+    event::Key,
     theme::{BaseColor, Color},
     utils::markup::StyledString,
     view::*,
@@ -12,6 +14,7 @@ use screeps_api::websocket::UserConsoleUpdate;
 use smart_default::SmartDefault;
 
 pub const CONSOLE_TEXT: &str = "console-text";
+pub const CONSOLE_INPUT: &str = "console-input";
 pub const MAX_LINES_TO_KEEP: u32 = 2000;
 
 #[derive(Clone, DebugStub, SmartDefault)]
@@ -24,24 +27,106 @@ pub struct ConsoleState {
     #[debug_stub = "content handle"]
     handle: TextContent,
     lines_in_lower: u32,
+    /// Expressions executed this session, oldest first.
+    history: Vec<String>,
+    /// Position in `history` currently shown in the input, or `None` when editing a new line.
+    history_pos: Option<usize>,
+    /// The new line being edited, saved while browsing history.
+    draft: String,
 }
 
 impl ConsoleState {
     pub fn view(&self) -> impl View + 'static {
-        ScrollView::new(TextView::new_with_content(self.handle.clone()))
+        let text = ScrollView::new(TextView::new_with_content(self.handle.clone()))
             .scroll_strategy(ScrollStrategy::StickToBottom)
             .show_scrollbars(false)
             .with_name(CONSOLE_TEXT)
+            .full_height();
+
+        let input = EditView::new()
+            .on_submit(|siv, text| super::sync_update(siv, |s| s.console_submit(text)))
+            .with_name(CONSOLE_INPUT);
+        let input = OnEventView::new(input)
+            .on_pre_event(Key::Up, |siv| {
+                super::sync_update(siv, |s| s.console_history(HistoryDirection::Older))
+            })
+            .on_pre_event(Key::Down, |siv| {
+                super::sync_update(siv, |s| s.console_history(HistoryDirection::Newer))
+            });
+
+        LinearLayout::new(Orientation::Vertical)
+            .child(text)
+            .child(
+                LinearLayout::new(Orientation::Horizontal)
+                    .child(TextView::new("> "))
+                    .child(input.full_width()),
+            )
             .resized(SizeConstraint::Fixed(80), SizeConstraint::Free)
     }
 
-    pub fn console_update(&mut self, srv: &mut Cursive, update: UserConsoleUpdate) {
+    /// Records a submitted expression, echoing it to the console.
+    ///
+    /// Returns the expression to execute, if it isn't blank.
+    pub fn submit(&mut self, srv: &mut Cursive, expression: &str) -> Option<String> {
+        srv.find_name::<EditView>(CONSOLE_INPUT)
+            .expect("expected to find CONSOLE_INPUT view")
+            .set_content("");
+        self.history_pos = None;
+        self.draft.clear();
+
+        if expression.trim().is_empty() {
+            return None;
+        }
+        if self.history.last().map(|s| &**s) != Some(expression) {
+            self.history.push(expression.to_owned());
+        }
+        self.add_styled_message(StyledString::styled(
+            format!("> {}\n", expression),
+            Color::Dark(BaseColor::Cyan),
+        ));
+        Some(expression.to_owned())
+    }
+
+    /// Replaces the input line with an older or newer history entry.
+    pub fn history(&mut self, srv: &mut Cursive, direction: HistoryDirection) {
+        let mut input = srv
+            .find_name::<EditView>(CONSOLE_INPUT)
+            .expect("expected to find CONSOLE_INPUT view");
+        let new_pos = match (direction, self.history_pos) {
+            (_, _) if self.history.is_empty() => return,
+            (HistoryDirection::Older, None) => {
+                self.draft = (*input.get_content()).clone();
+                Some(self.history.len() - 1)
+            }
+            (HistoryDirection::Older, Some(pos)) => Some(pos.saturating_sub(1)),
+            (HistoryDirection::Newer, None) => return,
+            (HistoryDirection::Newer, Some(pos)) if pos + 1 >= self.history.len() => None,
+            (HistoryDirection::Newer, Some(pos)) => Some(pos + 1),
+        };
+        self.history_pos = new_pos;
+        let content = match new_pos {
+            Some(pos) => self.history[pos].clone(),
+            None => self.draft.clone(),
+        };
+        input.set_content(content);
+    }
+
+    pub fn error(&mut self, srv: &mut Cursive, message: String) {
+        Self::stick_to_bottom_if_at_bottom(srv);
+        self.add_styled_message(Self::format_error_message(&None, message));
+    }
+
+    fn stick_to_bottom_if_at_bottom(srv: &mut Cursive) {
         let mut scroll = srv
             .find_name::<ScrollView<TextView>>(CONSOLE_TEXT)
             .expect("expected to find CONSOLE_TEXT view");
         if scroll.is_at_bottom() {
             scroll.set_scroll_strategy(ScrollStrategy::StickToBottom);
         }
+    }
+
+    pub fn console_update(&mut self, srv: &mut Cursive, update: UserConsoleUpdate) {
+        Self::stick_to_bottom_if_at_bottom(srv);
         match update {
             UserConsoleUpdate::Messages {
                 log_messages,
@@ -86,3 +171,9 @@ impl ConsoleState {
         StyledString::styled(format!("{}\n", msg), Color::Dark(BaseColor::Red))
     }
 }
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HistoryDirection {
+    Older,
+    Newer,
+}
@@ -0,0 +1,62 @@
+use cursive::{
+    theme::{BaseColor, Color},
+    utils::markup::StyledString,
+    view::*,
+    views::*,
+    Cursive,
+};
+
+use crate::room::{RoomEvent, RoomId};
+
+pub const EVENT_LIST: &str = "event-list";
+pub const MAX_EVENTS_TO_KEEP: usize = 500;
+
+/// Where an event happened, so that selecting it can move the cursor there.
+#[derive(Clone, Debug)]
+pub struct EventPos {
+    pub room_id: RoomId,
+    pub x: u32,
+    pub y: u32,
+}
+
+pub fn view() -> impl View + 'static {
+    let list = SelectView::<EventPos>::new()
+        .on_submit(|siv, pos| {
+            let pos = pos.clone();
+            super::sync_update(siv, move |s| s.jump_to(pos.room_id, pos.x, pos.y))
+        })
+        .with_name(EVENT_LIST);
+    Panel::new(
+        ScrollView::new(list)
+            .scroll_strategy(ScrollStrategy::StickToBottom)
+            .show_scrollbars(false),
+    )
+    .title("recent events")
+    .resized(SizeConstraint::Fixed(80), SizeConstraint::Fixed(12))
+}
+
+pub fn add_events(srv: &mut Cursive, room_id: RoomId, time: Option<u32>, events: Vec<RoomEvent>) {
+    let mut list = srv
+        .find_name::<SelectView<EventPos>>(EVENT_LIST)
+        .expect("expected to find EVENT_LIST view");
+    let header = match time {
+        Some(time) => format!("{} @ {}", room_id, time),
+        None => room_id.to_string(),
+    };
+    for event in events {
+        let mut label =
+            StyledString::styled(format!("[{}] ", header), Color::Dark(BaseColor::Blue));
+        label.append_plain(event.to_string());
+        list.add_item(
+            label,
+            EventPos {
+                room_id: room_id.clone(),
+                x: event.x,
+                y: event.y,
+            },
+        );
+    }
+    while list.len() > MAX_EVENTS_TO_KEEP {
+        list.remove_item(0);
+    }
+}
@@ -0,0 +1,46 @@
+use cursive::{
+    direction::Orientation,
+    event::Key,
+    theme::{BaseColor, Color},
+    utils::markup::StyledString,
+    view::*,
+    views::*,
+    Cursive,
+};
+
+use crate::room::Location;
+
+pub const GOTO_INPUT: &str = "goto-input";
+pub const GOTO_ERROR: &str = "goto-error";
+
+pub fn view() -> impl View + 'static {
+    let input = EditView::new()
+        .on_submit(submit)
+        .with_name(GOTO_INPUT)
+        .fixed_width(30);
+    let dialog = Dialog::around(
+        LinearLayout::new(Orientation::Vertical)
+            .child(TextView::new("W12N5, shard2/W12N5 or W12N5 25 13"))
+            .child(input)
+            .child(TextView::new("").with_name(GOTO_ERROR)),
+    )
+    .title("go to room")
+    .dismiss_button("Cancel");
+    OnEventView::new(dialog).on_event(Key::Esc, |siv| {
+        siv.pop_layer();
+    })
+}
+
+fn submit(siv: &mut Cursive, text: &str) {
+    match text.parse::<Location>() {
+        Ok(location) => {
+            siv.pop_layer();
+            super::sync_update(siv, |s| s.go_to(location));
+        }
+        Err(e) => {
+            siv.find_name::<TextView>(GOTO_ERROR)
+                .expect("expected to find GOTO_ERROR view")
+                .set_content(StyledString::styled(e, Color::Dark(BaseColor::Red)));
+        }
+    }
+}
@@ -12,7 +12,7 @@ use screeps_api::websocket::{
     RoomUserInfo,
 };
 
-use crate::room::{RoomObjectType, VisualObject};
+use crate::room::{kebab_of_debug, RoomObjectType, UnknownObject, VisualObject};
 
 pub fn info<T: Info + ?Sized>(thing: &T, state: &InfoInfo) -> String {
     let mut res = String::new();
@@ -89,6 +89,7 @@ impl Info for VisualObject {
             VisualObject::InterestingTerrain { ty, .. } => writeln!(out, "terrain: {}", ty),
             VisualObject::Flag(f) => writeln!(out, "flag {}", f.name),
             VisualObject::RoomObject(obj) => obj.fmt(out, state),
+            VisualObject::Unknown { obj, .. } => obj.fmt(out, state),
         }
     }
 }
@@ -131,6 +132,26 @@ impl Info for KnownRoomObject {
     }
 }
 
+impl Info for UnknownObject {
+    fn fmt<W: Write>(&self, out: &mut W, _state: &InfoInfo) -> fmt::Result {
+        match self.ty() {
+            Some(ty) => writeln!(out, "unknown {}:", ty)?,
+            None => writeln!(out, "unknown object:")?,
+        }
+        fmt_id(out, &self.id)?;
+        if let Some(fields) = self.data.as_object() {
+            for (key, value) in fields {
+                if ["_id", "type", "x", "y", "room"].contains(&&**key) {
+                    continue;
+                }
+                let value = value.to_string();
+                writeln!(out, " {}: {}", key, truncate(&value, 40))?;
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Info for Source {
     fn fmt<W: Write>(&self, out: &mut W, state: &InfoInfo) -> fmt::Result {
         writeln!(out, "source:")?;
@@ -601,8 +622,11 @@ fn fmt_disabled<W: Write>(out: &mut W, disabled: bool) -> fmt::Result {
     Ok(())
 }
 
-fn kebab_of_debug<T: fmt::Debug>(item: T) -> String {
-    string_morph::to_kebab_case(&format!("{:?}", item))
+fn truncate(s: &str, max_chars: usize) -> String {
+    match s.char_indices().nth(max_chars) {
+        Some((idx, _)) => format!("{}...", &s[..idx]),
+        None => s.to_owned(),
+    }
 }
 
 fn format_object_contents<W, T>(out: &mut W, contents: T) -> fmt::Result
@@ -0,0 +1,119 @@
+//! Which kinds of objects are drawn on the room map.
+use std::collections::HashSet;
+
+use crate::room::{RoomObjectType, VisualObject};
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum Layer {
+    Terrain,
+    Roads,
+    Ramparts,
+    /// Structures and the other objects that stay put, like sources and controllers.
+    Structures,
+    Creeps,
+    Flags,
+    /// Dropped resources and the tombstones holding what creeps carried.
+    Resources,
+    ConstructionSites,
+}
+
+impl Layer {
+    pub const ALL: &'static [Layer] = &[
+        Layer::Terrain,
+        Layer::Roads,
+        Layer::Ramparts,
+        Layer::Structures,
+        Layer::Creeps,
+        Layer::Flags,
+        Layer::Resources,
+        Layer::ConstructionSites,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Layer::Terrain => "terrain",
+            Layer::Roads => "roads",
+            Layer::Ramparts => "ramparts",
+            Layer::Structures => "structures",
+            Layer::Creeps => "creeps",
+            Layer::Flags => "flags",
+            Layer::Resources => "dropped resources",
+            Layer::ConstructionSites => "construction sites",
+        }
+    }
+
+    /// The key toggling this layer.
+    pub fn key(self) -> char {
+        match self {
+            Layer::Terrain => 'T',
+            Layer::Roads => 'R',
+            Layer::Ramparts => 'P',
+            Layer::Structures => 'S',
+            Layer::Creeps => 'C',
+            Layer::Flags => 'F',
+            Layer::Resources => 'D',
+            Layer::ConstructionSites => 'X',
+        }
+    }
+
+    /// The layer an object is drawn in, or `None` for objects srv doesn't know, which are
+    /// always drawn.
+    pub fn of(obj: &VisualObject) -> Option<Layer> {
+        let ty = match obj {
+            VisualObject::InterestingTerrain { .. } => return Some(Layer::Terrain),
+            VisualObject::Flag(_) => return Some(Layer::Flags),
+            VisualObject::RoomObject(obj) => RoomObjectType::of(obj),
+            VisualObject::Unknown { .. } => return None,
+        };
+        Some(match ty {
+            RoomObjectType::Road => Layer::Roads,
+            RoomObjectType::Rampart => Layer::Ramparts,
+            RoomObjectType::Creep => Layer::Creeps,
+            RoomObjectType::Resource | RoomObjectType::Tombstone => Layer::Resources,
+            RoomObjectType::ConstructionSite => Layer::ConstructionSites,
+            _ => Layer::Structures,
+        })
+    }
+}
+
+/// The layers hidden from the room map. Everything is shown by default.
+#[derive(Clone, Debug, Default)]
+pub struct Layers {
+    hidden: HashSet<Layer>,
+}
+
+impl Layers {
+    /// Hides the named layers, ignoring names that aren't layers.
+    pub fn from_hidden<'a>(names: impl IntoIterator<Item = &'a str>) -> Self {
+        let hidden = names
+            .into_iter()
+            .filter_map(|name| Layer::ALL.iter().find(|layer| layer.name() == name))
+            .cloned()
+            .collect();
+        Layers { hidden }
+    }
+
+    /// Names of the hidden layers, in menu order.
+    pub fn hidden_names(&self) -> Vec<&'static str> {
+        Layer::ALL
+            .iter()
+            .filter(|layer| self.hidden.contains(*layer))
+            .map(|layer| layer.name())
+            .collect()
+    }
+
+    pub fn is_shown(&self, layer: Layer) -> bool {
+        !self.hidden.contains(&layer)
+    }
+
+    pub fn toggle(&mut self, layer: Layer) {
+        if !self.hidden.remove(&layer) {
+            self.hidden.insert(layer);
+        }
+    }
+
+    /// Whether an object should be drawn.
+    pub fn shows(&self, obj: &VisualObject) -> bool {
+        Layer::of(obj).map_or(true, |layer| self.is_shown(layer))
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+
+use cursive::{
+    event::{Event, EventResult, Key},
+    theme::{BaseColor, Color, ColorStyle},
+    view::*,
+    views::*,
+    Printer, Vec2, XY,
+};
+use screeps_api::RoomName;
+
+use crate::room::{MapRoom, MapStats, RoomId};
+
+pub const WORLD_MAP: &str = "world-map";
+/// Rooms shown in each direction from the center of the map.
+pub const MAP_RADIUS: i32 = 4;
+
+const CELL_WIDTH: usize = 8;
+const CELL_HEIGHT: usize = 2;
+
+#[derive(Clone, Debug, Default)]
+pub struct MapState {
+    center: Option<RoomId>,
+    stats: MapStats,
+    /// Users with objects in each room, from the rooms' map views.
+    present_users: HashMap<RoomName, Vec<String>>,
+}
+
+impl MapState {
+    /// Starts a new map around `center`, returning the rooms it shows.
+    pub fn open(&mut self, center: RoomId) -> Vec<RoomId> {
+        *self = MapState::default();
+        let mut rooms = Vec::new();
+        for dy in -MAP_RADIUS..=MAP_RADIUS {
+            for dx in -MAP_RADIUS..=MAP_RADIUS {
+                rooms.push(center.offset(dx, dy));
+            }
+        }
+        self.center = Some(center);
+        rooms
+    }
+
+    pub fn add_stats(&mut self, stats: MapStats) {
+        self.stats.rooms.extend(stats.rooms);
+        self.stats.usernames.extend(stats.usernames);
+    }
+
+    pub fn set_present_users(&mut self, room_name: RoomName, users: Vec<String>) {
+        self.present_users.insert(room_name, users);
+    }
+}
+
+pub fn view() -> impl View + 'static {
+    Panel::new(WorldMap::default().with_name(WORLD_MAP))
+        .title("world map (enter: open room, esc: close)")
+}
+
+#[derive(Clone, Debug, smart_default::SmartDefault)]
+pub struct WorldMap {
+    /// Offset of the selected room from the center.
+    #[default(_code = "XY::new(0, 0)")]
+    cursor: XY<i32>,
+}
+
+impl WorldMap {
+    fn selected(&self) -> Option<RoomId> {
+        super::STATE.with(|state| {
+            let state = state.borrow();
+            let center = state.map.center.as_ref()?;
+            Some(center.offset(self.cursor.x, self.cursor.y))
+        })
+    }
+}
+
+impl View for WorldMap {
+    fn draw(&self, printer: &Printer) {
+        super::STATE.with(|state| {
+            let state = state.borrow();
+            let map = &state.map;
+            let center = match &map.center {
+                Some(center) => center,
+                None => return,
+            };
+            let my_id = state.user_info.as_ref().map(|info| &*info.user_id);
+
+            for dy in -MAP_RADIUS..=MAP_RADIUS {
+                for dx in -MAP_RADIUS..=MAP_RADIUS {
+                    let room_name = center.offset(dx, dy).room_name;
+                    let room = map.stats.rooms.get(&room_name);
+                    let present = map
+                        .present_users
+                        .get(&room_name)
+                        .map_or(&[][..], |users| &users[..]);
+
+                    let style = if XY::new(dx, dy) == self.cursor {
+                        ColorStyle::highlight()
+                    } else {
+                        room_style(room, my_id)
+                    };
+                    let owner = room
+                        .and_then(|r| r.owner.as_ref())
+                        .map(|id| map.stats.usernames.get(id).unwrap_or(id));
+                    let details = match (room, owner) {
+                        (Some(room), Some(owner)) if room.reserved() => format!("R {}", owner),
+                        (Some(room), Some(owner)) => format!("{} {}", room.level, owner),
+                        _ => String::new(),
+                    };
+                    // anyone besides us with objects in the room is worth a look
+                    let marker = if present.iter().any(|user| Some(&**user) != my_id) {
+                        "!"
+                    } else {
+                        " "
+                    };
+
+                    let x = (dx + MAP_RADIUS) as usize * CELL_WIDTH;
+                    let y = (dy + MAP_RADIUS) as usize * CELL_HEIGHT;
+                    printer.with_color(style, |printer| {
+                        printer.print((x, y), &cell_text(&room_name.to_string(), " "));
+                        printer.print((x, y + 1), &cell_text(&details, marker));
+                    });
+                }
+            }
+        });
+    }
+
+    fn on_event(&mut self, e: Event) -> EventResult {
+        let (dx, dy) = match e {
+            Event::Key(Key::Left) | Event::Char('h') => (-1, 0),
+            Event::Key(Key::Right) | Event::Char('l') => (1, 0),
+            Event::Key(Key::Up) | Event::Char('k') => (0, -1),
+            Event::Key(Key::Down) | Event::Char('j') => (0, 1),
+            Event::Key(Key::Enter) => {
+                let room_id = match self.selected() {
+                    Some(room_id) => room_id,
+                    None => return EventResult::Consumed(None),
+                };
+                return EventResult::with_cb(move |siv| {
+                    let room_id = room_id.clone();
+                    super::sync_update(siv, move |s| s.open_room_from_map(room_id))
+                });
+            }
+            Event::Key(Key::Esc) | Event::Char('m') => {
+                return EventResult::with_cb(|siv| super::sync_update(siv, |s| s.close_map()));
+            }
+            _ => return EventResult::Ignored,
+        };
+        let limit = XY::new(MAP_RADIUS, MAP_RADIUS);
+        self.cursor = (self.cursor + XY::new(dx, dy)).zip_map(limit, |c, l| c.max(-l).min(l));
+        EventResult::Consumed(None)
+    }
+
+    fn take_focus(&mut self, _dir: cursive::direction::Direction) -> bool {
+        true
+    }
+
+    fn required_size(&mut self, _: Vec2) -> Vec2 {
+        let side = (MAP_RADIUS * 2 + 1) as usize;
+        Vec2::new(side * CELL_WIDTH, side * CELL_HEIGHT)
+    }
+}
+
+/// Colors a room by who controls it, or by which zone it's in when nobody does. Owned rooms get
+/// lighter the higher their controller level is.
+fn room_style(room: Option<&MapRoom>, my_id: Option<&str>) -> ColorStyle {
+    let room = match room {
+        Some(room) => room,
+        None => return ColorStyle::secondary(),
+    };
+    let mine = my_id.is_some() && room.owner.as_deref() == my_id;
+    let back = match &room.owner {
+        Some(_) if mine && room.reserved() => Color::Light(BaseColor::Green),
+        Some(_) if room.reserved() => Color::Dark(BaseColor::Yellow),
+        Some(_) => {
+            let shade = level_shade(room.level);
+            let back = if mine {
+                Color::Rgb(0, shade, 0)
+            } else {
+                Color::Rgb(shade, 0, 0)
+            };
+            // white text stops being readable on the lightest shades
+            let front = if room.level > 4 {
+                Color::Dark(BaseColor::Black)
+            } else {
+                Color::Light(BaseColor::White)
+            };
+            return ColorStyle::new(front, back);
+        }
+        None if !room.open => Color::Dark(BaseColor::Black),
+        None if room.novice => Color::Dark(BaseColor::Blue),
+        None if room.respawn => Color::Dark(BaseColor::Cyan),
+        None => return ColorStyle::primary(),
+    };
+    ColorStyle::new(Color::Light(BaseColor::White), back)
+}
+
+/// Brightness of an owned room's background, from dark at RCL 1 to light at RCL 8.
+fn level_shade(level: u32) -> u8 {
+    0x38 + 0x18 * level.min(8) as u8
+}
+
+/// Pads or cuts text to fill one cell, leaving the last column for `marker`.
+fn cell_text(text: &str, marker: &str) -> String {
+    let text: String = text.chars().take(CELL_WIDTH - 1).collect();
+    format!("{:<width$}{}", text, marker, width = CELL_WIDTH - 1)
+}
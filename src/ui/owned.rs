@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+
+use cursive::{
+    theme::{BaseColor, Color},
+    utils::markup::StyledString,
+    view::*,
+    views::*,
+    Cursive,
+};
+
+use crate::room::{OwnedRoom, RoomId};
+
+pub const OWNED_ROOMS: &str = "owned-rooms";
+/// Rows of the list shown before it scrolls.
+const MAX_HEIGHT: usize = 10;
+
+/// The user's owned and reserved rooms, and which of them have someone else in them.
+#[derive(Clone, Debug, Default)]
+pub struct OwnedRooms {
+    rooms: Vec<OwnedRoom>,
+    hostiles: HashSet<RoomId>,
+}
+
+impl OwnedRooms {
+    pub fn view(&self) -> impl View + 'static {
+        let list = SelectView::<RoomId>::new()
+            .on_submit(|siv, room_id| {
+                let room_id = room_id.clone();
+                super::sync_update(siv, move |s| s.open_owned_room(room_id))
+            })
+            .with_name(OWNED_ROOMS);
+        Panel::new(ScrollView::new(list).show_scrollbars(false))
+            .title("owned rooms ([ ])")
+            .max_height(MAX_HEIGHT + 2)
+    }
+
+    pub fn set_rooms(&mut self, siv: &mut Cursive, rooms: Vec<OwnedRoom>, current: Option<&RoomId>) {
+        self.rooms = rooms;
+        self.refresh(siv, current);
+    }
+
+    /// Records whether anyone besides the user has objects in a room.
+    pub fn set_hostiles(
+        &mut self,
+        siv: &mut Cursive,
+        room_id: RoomId,
+        hostiles: bool,
+        current: Option<&RoomId>,
+    ) {
+        if !self.rooms.iter().any(|r| r.room_id == room_id) {
+            return;
+        }
+        let changed = if hostiles {
+            self.hostiles.insert(room_id)
+        } else {
+            self.hostiles.remove(&room_id)
+        };
+        if changed {
+            self.refresh(siv, current);
+        }
+    }
+
+    /// The room `step` places after `current` in the list, wrapping around.
+    ///
+    /// Starts from the top of the list when `current` isn't in it.
+    pub fn step_from(&self, current: Option<&RoomId>, step: isize) -> Option<RoomId> {
+        let len = self.rooms.len() as isize;
+        if len == 0 {
+            return None;
+        }
+        let idx = match self.rooms.iter().position(|r| Some(&r.room_id) == current) {
+            Some(idx) => (idx as isize + step).rem_euclid(len),
+            None => 0,
+        };
+        Some(self.rooms[idx as usize].room_id.clone())
+    }
+
+    /// Moves the list's selection to the viewed room, if it's in the list.
+    pub fn select(&self, siv: &mut Cursive, current: &RoomId) {
+        if let Some(idx) = self.rooms.iter().position(|r| r.room_id == *current) {
+            let mut list = siv
+                .find_name::<SelectView<RoomId>>(OWNED_ROOMS)
+                .expect("expected to find OWNED_ROOMS view");
+            // nothing happens on select, only on submit
+            let _ = list.set_selection(idx);
+        }
+    }
+
+    fn refresh(&self, siv: &mut Cursive, current: Option<&RoomId>) {
+        {
+            let mut list = siv
+                .find_name::<SelectView<RoomId>>(OWNED_ROOMS)
+                .expect("expected to find OWNED_ROOMS view");
+            list.clear();
+            for room in &self.rooms {
+                let level = match room.level {
+                    _ if room.reserved => "R".to_owned(),
+                    Some(level) => level.to_string(),
+                    None => "?".to_owned(),
+                };
+                let mut label = StyledString::plain(format!("{:<14} {:>2}", room.room_id, level));
+                if self.hostiles.contains(&room.room_id) {
+                    label.append_styled(" hostiles", Color::Light(BaseColor::Red));
+                }
+                list.add_item(label, room.room_id.clone());
+            }
+        }
+        if let Some(current) = current {
+            self.select(siv, current);
+        }
+    }
+}
@@ -0,0 +1,557 @@
+//! Glyphs and colors for drawing rooms, from a theme file over built-in defaults.
+//!
+//! ```toml
+//! # usernames whose objects are drawn as allies
+//! allies = ["someone"]
+//!
+//! [objects]
+//! spawn = { glyph = "P", fg = "light magenta" }
+//! storage = { fg = "yellow", bg = "black" }
+//! flag = { glyph = "F" } # flags and unknown objects are set here too
+//!
+//! [terrain]
+//! wall = { glyph = "#", fg = "white" }
+//!
+//! # colors for owned objects, replacing the object's own, and the mark next to them in wide mode
+//! [owners]
+//! mine = { fg = "light green" }
+//! hostile = { glyph = "!", fg = "light red", bg = "black" }
+//! ```
+//!
+//! Colors are cursive color names like `red` or `light red`, `#rrggbb` or `default`.
+use std::{
+    cmp,
+    collections::{HashMap, HashSet},
+    fs,
+    io::ErrorKind,
+    path::Path,
+};
+
+use cursive::theme::{BaseColor, Color, ColorStyle, ColorType, PaletteColor};
+use err_ctx::ResultExt;
+use log::warn;
+use unicode_width::UnicodeWidthChar;
+
+use crate::{
+    net::Error,
+    room::{InterestingTerrainType, RoomObjectType, VisualObject, VisualRoom},
+    storage,
+};
+
+/// Hits levels and stack counts in wide mode, with `+` for ten or more.
+const DIGITS: [&str; 11] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "+"];
+
+/// Users the server uses for invaders and source keepers.
+const NPC_USER_IDS: &[&str] = &["2", "3"];
+
+/// How an object's owner relates to the user.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Relation {
+    Mine,
+    Ally,
+    Hostile,
+    /// Invaders and source keepers.
+    Neutral,
+}
+
+impl Relation {
+    const ALL: &'static [Relation] = &[
+        Relation::Mine,
+        Relation::Ally,
+        Relation::Hostile,
+        Relation::Neutral,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Relation::Mine => "mine",
+            Relation::Ally => "ally",
+            Relation::Hostile => "hostile",
+            Relation::Neutral => "neutral",
+        }
+    }
+}
+
+/// Foreground and background colors, each left to the terminal's palette when unset.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Colors {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+}
+
+impl Colors {
+    fn foreground(color: Color) -> Self {
+        Colors {
+            fg: Some(color),
+            bg: None,
+        }
+    }
+
+    /// These colors, with any set in `over` replacing them.
+    fn with(self, over: Colors) -> Self {
+        Colors {
+            fg: over.fg.or(self.fg),
+            bg: over.bg.or(self.bg),
+        }
+    }
+
+    pub fn style(self) -> ColorStyle {
+        ColorStyle {
+            front: self
+                .fg
+                .map_or(ColorType::Palette(PaletteColor::Primary), ColorType::Color),
+            back: self
+                .bg
+                .map_or(ColorType::Palette(PaletteColor::View), ColorType::Color),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Glyph {
+    pub glyph: String,
+    pub colors: Colors,
+}
+
+impl Glyph {
+    fn new(glyph: &str, colors: Colors) -> Self {
+        Glyph {
+            glyph: glyph.to_owned(),
+            colors,
+        }
+    }
+}
+
+/// What to draw in one tile.
+#[derive(Copy, Clone, Debug)]
+pub struct Cell<'a> {
+    pub glyph: &'a str,
+    pub style: ColorStyle,
+}
+
+#[derive(Clone, Debug)]
+pub struct Theme {
+    objects: HashMap<RoomObjectType, Glyph>,
+    terrain: HashMap<InterestingTerrainType, Glyph>,
+    flag: Glyph,
+    unknown: Glyph,
+    /// Colors for owned objects, and the mark next to them in wide mode.
+    owners: HashMap<Relation, Glyph>,
+    /// Usernames of allies.
+    allies: HashSet<String>,
+    /// Whether to only draw single-width ASCII, for terminals which get other glyphs' widths
+    /// wrong.
+    ascii: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::new(false)
+    }
+}
+
+impl Theme {
+    fn new(ascii: bool) -> Self {
+        let objects = RoomObjectType::ALL
+            .iter()
+            .map(|ty| (ty.clone(), default_object(ty, ascii)))
+            .collect();
+        let terrain = InterestingTerrainType::ALL
+            .iter()
+            .map(|ty| (ty.clone(), default_terrain(ty, ascii)))
+            .collect();
+        let owners = Relation::ALL
+            .iter()
+            .map(|&relation| (relation, default_owner(relation, ascii)))
+            .collect();
+        Theme {
+            objects,
+            terrain,
+            flag: Glyph::new("F", Colors::foreground(Color::Light(BaseColor::White))),
+            unknown: Glyph::new("?", Colors::foreground(Color::Light(BaseColor::Magenta))),
+            owners,
+            allies: HashSet::new(),
+            ascii,
+        }
+    }
+
+    pub fn ascii(&self) -> bool {
+        self.ascii
+    }
+}
+
+/// The default glyph for an object, from the Unicode or ASCII set.
+fn default_object(ty: &RoomObjectType, ascii: bool) -> Glyph {
+    use RoomObjectType::*;
+    let light = |color| Colors::foreground(Color::Light(color));
+    let dark = |color| Colors::foreground(Color::Dark(color));
+    let (unicode, ascii_glyph, colors) = match ty {
+        ConstructionSite => ("△", "+", Colors::default()),
+        Container => ("▫", "u", dark(BaseColor::Yellow)),
+        Controller => ("C", "C", light(BaseColor::White)),
+        Creep => ("●", "o", Colors::default()),
+        Extension => ("E", "E", Colors::default()),
+        Extractor => ("X", "X", Colors::default()),
+        KeeperLair => ("K", "K", dark(BaseColor::Red)),
+        Lab => ("L", "L", Colors::default()),
+        Link => ("I", "I", Colors::default()),
+        Mineral => ("M", "M", light(BaseColor::Cyan)),
+        Nuker => ("N", "N", Colors::default()),
+        Observer => ("O", "O", Colors::default()),
+        Portal => ("@", "@", light(BaseColor::Blue)),
+        PowerBank => ("B", "B", light(BaseColor::Red)),
+        PowerSpawn => ("R", "R", Colors::default()),
+        Rampart => ("▒", "%", dark(BaseColor::Green)),
+        Resource => ("▪", "*", light(BaseColor::Yellow)),
+        Road => ("╬", "=", light(BaseColor::Black)),
+        Source => ("S", "S", light(BaseColor::Yellow)),
+        Spawn => ("P", "P", Colors::default()),
+        Storage => ("$", "$", Colors::default()),
+        Terminal => ("T", "T", Colors::default()),
+        Tower => ("♜", "A", Colors::default()),
+        // not the coffin emoji, which most terminals draw two columns wide
+        Tombstone => ("†", "x", light(BaseColor::Black)),
+        Wall => ("W", "W", Colors::default()),
+    };
+    Glyph::new(if ascii { ascii_glyph } else { unicode }, colors)
+}
+
+fn default_terrain(ty: &InterestingTerrainType, ascii: bool) -> Glyph {
+    let (unicode, ascii_glyph, colors) = match ty {
+        InterestingTerrainType::Swamp => {
+            ("⌇", "~", Colors::foreground(Color::Dark(BaseColor::Green)))
+        }
+        InterestingTerrainType::Wall => ("█", "#", Colors::default()),
+    };
+    Glyph::new(if ascii { ascii_glyph } else { unicode }, colors)
+}
+
+fn default_owner(relation: Relation, ascii: bool) -> Glyph {
+    let (unicode, ascii_glyph, colors) = match relation {
+        Relation::Mine => (" ", " ", Colors::foreground(Color::Light(BaseColor::Green))),
+        Relation::Ally => ("·", ".", Colors::foreground(Color::Light(BaseColor::Cyan))),
+        Relation::Hostile => ("!", "!", Colors::foreground(Color::Light(BaseColor::Red))),
+        Relation::Neutral => ("·", ".", Colors::foreground(Color::Dark(BaseColor::Yellow))),
+    };
+    Glyph::new(if ascii { ascii_glyph } else { unicode }, colors)
+}
+
+impl Theme {
+    /// Loads a theme file over the built-in theme, or `theme.toml` in the config directory if
+    /// no file is given.
+    ///
+    /// A missing file is only an error when it was given. In ASCII mode, the file's non-ASCII
+    /// glyphs are ignored.
+    pub fn load(path: Option<&Path>, ascii: bool) -> Result<Self, Error> {
+        let (path, required) = match path {
+            Some(path) => (path.to_owned(), true),
+            None => match storage::config_dir() {
+                Some(dir) => (dir.join("theme.toml"), false),
+                None => return Ok(Theme::new(ascii)),
+            },
+        };
+        let text = match fs::read_to_string(&path) {
+            Err(e) if e.kind() == ErrorKind::NotFound && !required => return Ok(Theme::new(ascii)),
+            res => res.with_ctx(|_| format!("reading {}", path.display()))?,
+        };
+        let file = text
+            .parse::<toml::Value>()
+            .with_ctx(|_| format!("parsing {}", path.display()))?;
+        let mut theme = Theme::new(ascii);
+        theme
+            .apply(&file)
+            .with_ctx(|_| format!("in {}", path.display()))?;
+        Ok(theme)
+    }
+
+    fn apply(&mut self, file: &toml::Value) -> Result<(), Error> {
+        let ascii = self.ascii;
+        let table = |key: &str| -> Result<Option<&toml::value::Table>, Error> {
+            match file.get(key) {
+                None => Ok(None),
+                Some(toml::Value::Table(table)) => Ok(Some(table)),
+                Some(_) => Err(format!("{} must be a table", key).into()),
+            }
+        };
+
+        for (name, value) in table("objects")?.into_iter().flatten() {
+            let what = format!("objects.{}", name);
+            let glyph = match &**name {
+                "flag" => &mut self.flag,
+                "unknown" => &mut self.unknown,
+                _ => {
+                    let ty = RoomObjectType::ALL
+                        .iter()
+                        .find(|ty| ty.name() == name)
+                        .ok_or_else(|| format!("unknown object type {:?}", name))?;
+                    self.objects
+                        .get_mut(ty)
+                        .expect("all object types have glyphs")
+                }
+            };
+            apply_glyph(glyph, value, &what, ascii)?;
+        }
+        for (name, value) in table("terrain")?.into_iter().flatten() {
+            let ty = InterestingTerrainType::ALL
+                .iter()
+                .find(|ty| ty.to_string() == *name)
+                .ok_or_else(|| format!("unknown terrain type {:?}", name))?;
+            let glyph = self
+                .terrain
+                .get_mut(ty)
+                .expect("all terrain types have glyphs");
+            apply_glyph(glyph, value, &format!("terrain.{}", name), ascii)?;
+        }
+        for (name, value) in table("owners")?.into_iter().flatten() {
+            let relation = Relation::ALL
+                .iter()
+                .find(|relation| relation.name() == name)
+                .ok_or_else(|| {
+                    format!(
+                        "unknown owner {:?}, expected mine, ally, hostile or neutral",
+                        name
+                    )
+                })?;
+            let glyph = self
+                .owners
+                .get_mut(relation)
+                .expect("all owners have marks");
+            apply_glyph(glyph, value, &format!("owners.{}", name), ascii)?;
+        }
+        match file.get("allies") {
+            None => (),
+            Some(toml::Value::Array(allies)) => {
+                for ally in allies {
+                    let ally = ally.as_str().ok_or("allies must be usernames")?;
+                    self.allies.insert(ally.to_owned());
+                }
+            }
+            Some(_) => return Err("allies must be a list of usernames".into()),
+        }
+        Ok(())
+    }
+
+    /// The glyph and colors for a tile, from the top object on it.
+    pub fn cell<'a, 'b>(
+        &'a self,
+        room: &VisualRoom,
+        objs: impl Iterator<Item = &'b VisualObject>,
+        user_id: Option<&str>,
+    ) -> Cell<'a> {
+        let top = match objs.last() {
+            Some(top) => top,
+            None => {
+                return Cell {
+                    glyph: " ",
+                    style: ColorStyle::primary(),
+                }
+            }
+        };
+        let glyph = match top {
+            VisualObject::InterestingTerrain { ty, .. } => &self.terrain[ty],
+            VisualObject::Flag(_) => &self.flag,
+            VisualObject::RoomObject(obj) => &self.objects[&RoomObjectType::of(obj)],
+            VisualObject::Unknown { .. } => &self.unknown,
+        };
+        let colors = match top.owner() {
+            Some(owner) => glyph
+                .colors
+                .with(self.owners[&self.relation(room, owner, user_id)].colors),
+            None => glyph.colors,
+        };
+        Cell {
+            glyph: &glyph.glyph,
+            style: colors.style(),
+        }
+    }
+
+    /// The color a tile is drawn with in compact mode, where each cell is split between two
+    /// tiles and only shows who owns the top object, or what kind of thing it is.
+    pub fn compact_color<'b>(
+        &self,
+        room: &VisualRoom,
+        objs: impl Iterator<Item = &'b VisualObject>,
+        user_id: Option<&str>,
+    ) -> ColorType {
+        let top = match objs.last() {
+            Some(top) => top,
+            None => return ColorType::Palette(PaletteColor::View),
+        };
+        // owned objects show who owns them, like the owner marks in wide mode
+        let owner_color = top
+            .owner()
+            .and_then(|owner| self.owners[&self.relation(room, owner, user_id)].colors.fg);
+        if let Some(color) = owner_color {
+            return ColorType::Color(color);
+        }
+        let color = match top {
+            VisualObject::InterestingTerrain { ty, .. } => match ty {
+                InterestingTerrainType::Swamp => Color::Dark(BaseColor::Green),
+                InterestingTerrainType::Wall => Color::Light(BaseColor::Black),
+            },
+            VisualObject::Flag(_) => Color::Light(BaseColor::Magenta),
+            VisualObject::RoomObject(obj) => match RoomObjectType::of(obj) {
+                RoomObjectType::Road => Color::Dark(BaseColor::White),
+                RoomObjectType::Creep => Color::Light(BaseColor::White),
+                RoomObjectType::Resource | RoomObjectType::Tombstone => {
+                    Color::Light(BaseColor::Yellow)
+                }
+                RoomObjectType::ConstructionSite => Color::Light(BaseColor::Blue),
+                _ => Color::Dark(BaseColor::Blue),
+            },
+            VisualObject::Unknown { .. } => Color::Dark(BaseColor::Magenta),
+        };
+        ColorType::Color(color)
+    }
+
+    /// What to draw next to a tile's glyph in wide mode: how many objects are stacked on it, how
+    /// damaged the top one is, or who owns it.
+    pub fn indicator<'a, 'b>(
+        &'a self,
+        room: &VisualRoom,
+        objs: impl Iterator<Item = &'b VisualObject> + Clone,
+        user_id: Option<&str>,
+    ) -> Cell<'a> {
+        let blank = Cell {
+            glyph: " ",
+            style: ColorStyle::primary(),
+        };
+        let stacked = objs
+            .clone()
+            .filter(|obj| match obj {
+                VisualObject::InterestingTerrain { .. } => false,
+                _ => true,
+            })
+            .count();
+        if stacked > 1 {
+            return Cell {
+                glyph: DIGITS[cmp::min(stacked, DIGITS.len() - 1)],
+                style: ColorStyle::secondary(),
+            };
+        }
+        let top = match objs.last() {
+            Some(top) => top,
+            None => return blank,
+        };
+
+        let is_barrier = match top {
+            VisualObject::RoomObject(obj) => match RoomObjectType::of(obj) {
+                RoomObjectType::Wall | RoomObjectType::Rampart => true,
+                _ => false,
+            },
+            _ => false,
+        };
+        // walls and ramparts are nowhere near their maximum, so they'd always look damaged
+        match top.hits() {
+            Some((hits, hits_max)) if !is_barrier && hits_max > 0 && hits < hits_max => {
+                let level = (i64::from(cmp::max(hits, 0)) * 10 / i64::from(hits_max)) as usize;
+                let color = match level {
+                    0..=2 => BaseColor::Red,
+                    3..=6 => BaseColor::Yellow,
+                    _ => BaseColor::Green,
+                };
+                return Cell {
+                    glyph: DIGITS[level],
+                    style: Colors::foreground(Color::Light(color)).style(),
+                };
+            }
+            _ => (),
+        }
+
+        match top.owner() {
+            Some(owner) => {
+                let mark = &self.owners[&self.relation(room, owner, user_id)];
+                Cell {
+                    glyph: &mark.glyph,
+                    style: mark.colors.style(),
+                }
+            }
+            None => blank,
+        }
+    }
+
+    fn relation(&self, room: &VisualRoom, owner: &str, user_id: Option<&str>) -> Relation {
+        if Some(owner) == user_id {
+            return Relation::Mine;
+        }
+        if NPC_USER_IDS.contains(&owner) {
+            return Relation::Neutral;
+        }
+        let username = room.users.get(owner).and_then(|u| u.username.as_ref());
+        match username {
+            Some(username) if self.allies.contains(username) => Relation::Ally,
+            _ => Relation::Hostile,
+        }
+    }
+}
+
+fn apply_glyph(
+    glyph: &mut Glyph,
+    value: &toml::Value,
+    what: &str,
+    ascii: bool,
+) -> Result<(), Error> {
+    let (text, colors) = parse_glyph(value, what)?;
+    if let Some(text) = text {
+        if !one_column(&text, false) {
+            return Err(format!(
+                "glyph {:?} for {} must be a single character one column wide",
+                text, what
+            )
+            .into());
+        }
+        if ascii && !one_column(&text, true) {
+            // the same theme can be used with and without ASCII mode
+            warn!(
+                "ignoring non-ASCII glyph {:?} for {} in ASCII mode",
+                text, what
+            );
+        } else {
+            glyph.glyph = text;
+        }
+    }
+    glyph.colors = glyph.colors.with(colors);
+    Ok(())
+}
+
+/// Reads `{ glyph = "..", fg = "..", bg = ".." }`, all optional.
+fn parse_glyph(value: &toml::Value, what: &str) -> Result<(Option<String>, Colors), Error> {
+    let table = value
+        .as_table()
+        .ok_or_else(|| format!("{} must be a table", what))?;
+    let mut glyph = None;
+    let mut colors = Colors::default();
+    for (key, value) in table {
+        let text = value
+            .as_str()
+            .ok_or_else(|| format!("{}.{} must be a string", what, key))?;
+        match &**key {
+            "glyph" => glyph = Some(text.to_owned()),
+            "fg" => colors.fg = Some(parse_color(text, what)?),
+            "bg" => colors.bg = Some(parse_color(text, what)?),
+            _ => return Err(format!("unknown setting {}.{}", what, key).into()),
+        }
+    }
+    Ok((glyph, colors))
+}
+
+/// Whether a glyph is a single character taking exactly one column, so that it can't push the
+/// rest of a room's row out of line.
+fn one_column(glyph: &str, ascii: bool) -> bool {
+    let mut chars = glyph.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if ascii => c == ' ' || c.is_ascii_graphic(),
+        // this also rules out emoji with variation selectors, which are often drawn two wide
+        (Some(c), None) => c.width() == Some(1),
+        _ => false,
+    }
+}
+
+fn parse_color(text: &str, what: &str) -> Result<Color, Error> {
+    match text {
+        "default" => Ok(Color::TerminalDefault),
+        _ => {
+            Color::parse(text).ok_or_else(|| format!("invalid color {:?} in {}", text, what).into())
+        }
+    }
+}
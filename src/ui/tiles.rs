@@ -0,0 +1,156 @@
+//! How a room's tiles are laid out in terminal cells.
+use std::str::FromStr;
+
+use cursive::{Vec2, XY};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TileMode {
+    /// One cell per tile.
+    Normal,
+    /// Two cells per tile, a glyph and an indicator, so that rooms look about square.
+    Wide,
+    /// Two tiles per cell, one above the other, drawn as colored half blocks.
+    Compact,
+}
+
+impl Default for TileMode {
+    fn default() -> Self {
+        TileMode::Normal
+    }
+}
+
+impl FromStr for TileMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(TileMode::Normal),
+            "wide" => Ok(TileMode::Wide),
+            "compact" => Ok(TileMode::Compact),
+            _ => Err(format!(
+                "unknown tile mode {:?}, expected normal, wide or compact",
+                s
+            )),
+        }
+    }
+}
+
+impl TileMode {
+    /// Terminal columns per tile.
+    pub fn tile_width(self) -> usize {
+        match self {
+            TileMode::Normal | TileMode::Compact => 1,
+            TileMode::Wide => 2,
+        }
+    }
+
+    /// Tiles stacked top to bottom in one terminal cell.
+    fn tiles_per_cell_height(self) -> usize {
+        match self {
+            TileMode::Normal | TileMode::Wide => 1,
+            TileMode::Compact => 2,
+        }
+    }
+
+    /// Size of one room in the neighborhood view, including one border line on each axis.
+    pub fn room_cell(self) -> Vec2 {
+        Vec2::new(
+            50 * self.tile_width() + 1,
+            50 / self.tiles_per_cell_height() + 1,
+        )
+    }
+
+    /// Where a tile is drawn, relative to the room's border.
+    pub fn tile_pos(self, x: usize, y: usize) -> Vec2 {
+        Vec2::new(
+            1 + x * self.tile_width(),
+            1 + y / self.tiles_per_cell_height(),
+        )
+    }
+
+    /// The tile drawn at a position relative to the room's border, which may be outside the
+    /// room. In compact mode this is the upper of the two tiles.
+    pub fn tile_at(self, pos: XY<i32>) -> XY<i32> {
+        XY::new(
+            (pos.x - 1).div_euclid(self.tile_width() as i32),
+            (pos.y - 1) * self.tiles_per_cell_height() as i32,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cursive::{Vec2, XY};
+
+    use super::TileMode;
+
+    const MODES: [TileMode; 3] = [TileMode::Normal, TileMode::Wide, TileMode::Compact];
+
+    fn tile_at(mode: TileMode, x: i32, y: i32) -> (i32, i32) {
+        let tile = mode.tile_at(XY::new(x, y));
+        (tile.x, tile.y)
+    }
+
+    #[test]
+    fn tile_pos_skips_the_border() {
+        for &mode in &MODES {
+            assert_eq!(mode.tile_pos(0, 0), Vec2::new(1, 1));
+        }
+        assert_eq!(TileMode::Normal.tile_pos(49, 49), Vec2::new(50, 50));
+        assert_eq!(TileMode::Wide.tile_pos(3, 5), Vec2::new(7, 6));
+        assert_eq!(TileMode::Wide.tile_pos(49, 49), Vec2::new(99, 50));
+        assert_eq!(TileMode::Compact.tile_pos(0, 1), Vec2::new(1, 1));
+        assert_eq!(TileMode::Compact.tile_pos(0, 2), Vec2::new(1, 2));
+        assert_eq!(TileMode::Compact.tile_pos(7, 49), Vec2::new(8, 25));
+    }
+
+    #[test]
+    fn tile_at_inside_the_room() {
+        assert_eq!(tile_at(TileMode::Normal, 1, 1), (0, 0));
+        assert_eq!(tile_at(TileMode::Normal, 50, 50), (49, 49));
+        // both columns of a wide tile belong to it
+        assert_eq!(tile_at(TileMode::Wide, 1, 1), (0, 0));
+        assert_eq!(tile_at(TileMode::Wide, 2, 1), (0, 0));
+        assert_eq!(tile_at(TileMode::Wide, 3, 1), (1, 0));
+        assert_eq!(tile_at(TileMode::Wide, 100, 50), (49, 49));
+        // a compact cell gives its upper tile
+        assert_eq!(tile_at(TileMode::Compact, 1, 1), (0, 0));
+        assert_eq!(tile_at(TileMode::Compact, 1, 2), (0, 2));
+        assert_eq!(tile_at(TileMode::Compact, 50, 25), (49, 48));
+    }
+
+    #[test]
+    fn tile_at_before_the_room() {
+        assert_eq!(tile_at(TileMode::Normal, 0, 0), (-1, -1));
+        assert_eq!(tile_at(TileMode::Normal, -3, -3), (-4, -4));
+        // rounds toward the left, so the column left of the border is still a whole tile
+        assert_eq!(tile_at(TileMode::Wide, 0, 0), (-1, -1));
+        assert_eq!(tile_at(TileMode::Wide, -1, 1), (-1, 0));
+        assert_eq!(tile_at(TileMode::Wide, -2, 1), (-2, 0));
+        assert_eq!(tile_at(TileMode::Compact, 0, 0), (-1, -2));
+        assert_eq!(tile_at(TileMode::Compact, 1, -1), (0, -4));
+    }
+
+    #[test]
+    fn tile_at_in_neighbor_rooms() {
+        assert_eq!(tile_at(TileMode::Normal, 51, 1), (50, 0));
+        assert_eq!(tile_at(TileMode::Normal, 1, 52), (0, 51));
+        assert_eq!(tile_at(TileMode::Wide, 101, 1), (50, 0));
+        assert_eq!(tile_at(TileMode::Wide, 103, 51), (51, 50));
+        assert_eq!(tile_at(TileMode::Compact, 51, 26), (50, 50));
+        assert_eq!(tile_at(TileMode::Compact, 1, 27), (0, 52));
+    }
+
+    #[test]
+    fn tile_at_finds_drawn_tiles() {
+        for &mode in &MODES {
+            let per_cell = mode.tiles_per_cell_height();
+            for &(x, y) in &[(0, 0), (1, 1), (25, 30), (49, 49)] {
+                let pos = mode.tile_pos(x, y);
+                let tile = mode.tile_at(XY::new(pos.x as i32, pos.y as i32));
+                let top = y - y % per_cell;
+                assert_eq!((tile.x, tile.y), (x as i32, top as i32), "{:?}", mode);
+            }
+        }
+    }
+}
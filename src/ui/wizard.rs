@@ -0,0 +1,210 @@
+//! The first-run setup wizard, shown when there's no token to connect with.
+use std::thread;
+
+use bytes::Bytes;
+use cursive::{
+    direction::Orientation,
+    event::Key,
+    theme::{BaseColor, Color},
+    utils::markup::StyledString,
+    view::*,
+    views::*,
+    Cursive,
+};
+use log::info;
+
+use crate::{
+    config::Config,
+    net::{self, AccountCheck, Credentials, Error},
+};
+
+pub const WIZARD_SERVER: &str = "wizard-server";
+pub const WIZARD_TOKEN: &str = "wizard-token";
+pub const WIZARD_USERNAME: &str = "wizard-username";
+pub const WIZARD_PASSWORD: &str = "wizard-password";
+pub const WIZARD_PROFILE: &str = "wizard-profile";
+pub const WIZARD_STATUS: &str = "wizard-status";
+
+const DEFAULT_PRIVATE_SERVER: &str = "http://localhost:21025/api/";
+const LABEL_WIDTH: usize = 10;
+
+/// Everything chosen on the first page.
+#[derive(Clone, Debug)]
+struct Choices {
+    /// `None` for the official server.
+    server: Option<String>,
+    credentials: Credentials,
+    profile: String,
+}
+
+pub fn view(config: Config) -> impl View + 'static {
+    let mut server = RadioGroup::new();
+    let mut auth = RadioGroup::new();
+
+    let field = |label: &str, view: EditView, name: &str| {
+        LinearLayout::new(Orientation::Horizontal)
+            .child(TextView::new(label).fixed_width(LABEL_WIDTH))
+            .child(view.with_name(name).fixed_width(40))
+    };
+
+    let layout = LinearLayout::new(Orientation::Vertical)
+        .child(TextView::new(
+            "No token was given. Choose a server and how to sign in, \
+             and it'll be saved as a profile for next time.",
+        ))
+        .child(DummyView)
+        .child(server.button(false, "Official server (screeps.com)"))
+        .child(
+            LinearLayout::new(Orientation::Horizontal)
+                .child(server.button(true, "Custom:").fixed_width(LABEL_WIDTH + 2))
+                .child(
+                    EditView::new()
+                        .content(DEFAULT_PRIVATE_SERVER)
+                        .with_name(WIZARD_SERVER)
+                        .fixed_width(38),
+                ),
+        )
+        .child(DummyView)
+        .child(auth.button(false, "Auth token (from the account settings page)"))
+        .child(field("Token", EditView::new().secret(), WIZARD_TOKEN))
+        .child(auth.button(true, "Username and password (private servers)"))
+        .child(field("Username", EditView::new(), WIZARD_USERNAME))
+        .child(field("Password", EditView::new().secret(), WIZARD_PASSWORD))
+        .child(DummyView)
+        .child(field(
+            "Profile",
+            EditView::new().content("default"),
+            WIZARD_PROFILE,
+        ))
+        .child(TextView::new("").with_name(WIZARD_STATUS));
+
+    let dialog = Dialog::around(layout)
+        .title("srv setup")
+        .button("Check", move |siv| {
+            let choices = read_choices(siv, *server.selection(), *auth.selection());
+            match choices {
+                Ok(choices) => check(siv, config.clone(), choices),
+                Err(e) => set_status(siv, StyledString::styled(e, Color::Dark(BaseColor::Red))),
+            }
+        })
+        .button("Quit", |siv| siv.quit());
+    OnEventView::new(dialog).on_event(Key::Esc, |siv| siv.quit())
+}
+
+fn read_choices(siv: &mut Cursive, custom_server: bool, sign_in: bool) -> Result<Choices, String> {
+    let mut text = |name: &str| -> String {
+        siv.find_name::<EditView>(name)
+            .unwrap_or_else(|| panic!("expected to find {} view", name))
+            .get_content()
+            .trim()
+            .to_owned()
+    };
+    let non_empty = |value: String, what: &str| match &*value {
+        "" => Err(format!("{} is required", what)),
+        _ => Ok(value),
+    };
+
+    let server = if custom_server {
+        Some(non_empty(text(WIZARD_SERVER), "a server URL")?)
+    } else {
+        None
+    };
+    let credentials = if sign_in {
+        Credentials::SignIn {
+            username: non_empty(text(WIZARD_USERNAME), "a username")?,
+            password: text(WIZARD_PASSWORD),
+        }
+    } else {
+        let token = non_empty(text(WIZARD_TOKEN), "a token")?;
+        Credentials::Token(Bytes::copy_from_slice(token.as_bytes()))
+    };
+    Ok(Choices {
+        server,
+        credentials,
+        profile: non_empty(text(WIZARD_PROFILE), "a profile name")?,
+    })
+}
+
+fn set_status(siv: &mut Cursive, status: StyledString) {
+    siv.find_name::<TextView>(WIZARD_STATUS)
+        .expect("expected to find WIZARD_STATUS view")
+        .set_content(status);
+}
+
+/// Checks the choices against the server off the UI thread, then moves on to picking a shard.
+fn check(siv: &mut Cursive, config: Config, choices: Choices) {
+    set_status(siv, StyledString::plain("checking..."));
+    let ui = siv.cb_sink().clone();
+    thread::spawn(move || {
+        let res = net::check_account(choices.server.as_ref().map(|s| &**s), &choices.credentials);
+        // the UI is gone if this fails, so there's nobody to tell
+        let _ = ui.send(Box::new(move |siv| checked(siv, config, choices, res)));
+    });
+}
+
+fn checked(siv: &mut Cursive, config: Config, choices: Choices, res: Result<AccountCheck, Error>) {
+    let account = match res {
+        Ok(account) => account,
+        Err(e) => {
+            let message = format!("check failed: {}", e);
+            set_status(
+                siv,
+                StyledString::styled(message, Color::Dark(BaseColor::Red)),
+            );
+            return;
+        }
+    };
+    set_status(siv, StyledString::plain(""));
+
+    let mut shards = SelectView::<Option<String>>::new();
+    if account.shards.is_empty() {
+        shards.add_item("(no shards)", None);
+    }
+    for shard in account.shards {
+        shards.add_item(shard.clone(), Some(shard));
+    }
+    let shards = shards.on_submit(move |siv, shard: &Option<String>| {
+        finish(siv, config.clone(), choices.clone(), shard.clone())
+    });
+
+    let dialog = Dialog::around(
+        LinearLayout::new(Orientation::Vertical)
+            .child(TextView::new(format!(
+                "Signed in as {}. Choose the default shard:",
+                account.username
+            )))
+            .child(ScrollView::new(shards).max_height(10)),
+    )
+    .title("srv setup")
+    .dismiss_button("Back");
+    siv.add_layer(OnEventView::new(dialog).on_event(Key::Esc, |siv| {
+        siv.pop_layer();
+    }));
+}
+
+/// Saves the profile and starts the UI and connection with it.
+fn finish(siv: &mut Cursive, mut config: Config, choices: Choices, shard: Option<String>) {
+    config.server = choices.server;
+    match choices.credentials {
+        Credentials::Token(token) => config.auth_token = Some(token),
+        Credentials::SignIn { username, password } => {
+            config.username = Some(username);
+            config.password = Some(password);
+        }
+    }
+    config.shard = shard;
+
+    match config.save_profile(&choices.profile) {
+        Ok(path) => info!("saved profile {:?} to {}", choices.profile, path.display()),
+        Err(e) => {
+            siv.add_layer(Dialog::info(format!("couldn't save profile: {}", e)));
+            return;
+        }
+    }
+
+    // the shard list and the wizard itself
+    siv.pop_layer();
+    siv.pop_layer();
+    super::setup(siv, &config);
+    net::spawn(config, siv.cb_sink().clone());
+}